@@ -0,0 +1,20 @@
+#version 100
+// Vertex shader for drawing lights into the light buffer
+
+precision highp float;
+
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying vec2 worldPos;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+// Lights are drawn as their visibility polygon, in world coordinates,
+// so the fragment shader just needs to know where in the world it is.
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1.0);
+    worldPos = position.xy;
+}
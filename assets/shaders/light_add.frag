@@ -0,0 +1,11 @@
+#version 100
+// Adds one light's finished buffer into the light buffer
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform sampler2D Texture;
+
+void main() {
+    gl_FragColor = vec4((texture2D(Texture, uv) * color).rgb, 0.0);
+}
@@ -0,0 +1,16 @@
+#version 100
+// Normal circular light that falls off equally in all directions
+
+precision highp float;
+
+varying vec2 worldPos;
+
+uniform vec2 LightPos;
+uniform vec3 LightColor;
+// How harshly the light falls off; lower is wider
+uniform float Falloff;
+
+void main() {
+    float l = 1.0 - Falloff * distance(worldPos, LightPos);
+    gl_FragColor = vec4(LightColor * max(l, 0.0), 0.0);
+}
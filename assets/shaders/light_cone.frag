@@ -0,0 +1,29 @@
+#version 100
+// Cone-shaped light, stolen from Upper Crust
+
+precision highp float;
+
+varying vec2 worldPos;
+
+uniform vec2 LightPos;
+uniform vec3 LightColor;
+// How harshly the light falls off; lower is wider
+uniform float Falloff;
+uniform float Spread;
+// Angle the cone points in, in radians
+uniform float Facing;
+
+void main() {
+    // Untransform the position by the facing dir
+    float c = cos(-Facing);
+    float s = sin(-Facing);
+    vec2 offset = worldPos - LightPos;
+    vec2 delta = vec2(c * offset.x - s * offset.y, s * offset.x + c * offset.y);
+
+    float l = 0.0;
+    if (delta.x > 0.0) {
+        l = max(1.0 - Falloff * delta.x - abs(delta.y) / delta.x / Spread, 0.0);
+    }
+    l += max(1.0 - Falloff * dot(delta, delta) * 5.0, 0.0);
+    gl_FragColor = vec4(LightColor * clamp(l, 0.0, 1.0), 0.0);
+}
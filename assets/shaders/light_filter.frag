@@ -0,0 +1,10 @@
+#version 100
+// Tints the light behind something see-through.
+// This gets multiplied into what's already there, so white lets everything through.
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+void main() {
+    gl_FragColor = color;
+}
@@ -0,0 +1,182 @@
+//! Loading sprite sheets exported from aseprite.
+//!
+//! Export them with File > Export Sprite Sheet, with the JSON data set to "Array"
+//! and "Tags" turned on. Each tag becomes a clip.
+//! Tags named like `walk_right` are the `right` variant of the `walk` clip.
+
+use ahash::AHashMap;
+use anyhow::{bail, Context};
+use macroquad::prelude::{Rect, Texture2D};
+use serde::Deserialize;
+
+/// A texture with frames of animation on it, and named clips made out of those frames.
+pub struct SpriteSheet {
+    pub texture: Texture2D,
+    frames: Vec<SheetFrame>,
+    clips: AHashMap<String, Clip>,
+}
+
+/// One frame on a sprite sheet.
+#[derive(Debug, Clone, Copy)]
+pub struct SheetFrame {
+    /// Where the frame is on the texture, in pixels.
+    pub region: Rect,
+    /// How long the frame stays up when it's animated, in seconds.
+    pub duration: f32,
+}
+
+/// A named run of frames that plays on a loop.
+#[derive(Debug, Clone)]
+pub struct Clip {
+    /// Indices into the sheet's frames, in the order they play.
+    /// Ping-pong tags are already unrolled.
+    frames: Vec<usize>,
+}
+
+impl Clip {
+    pub fn frames(&self) -> &[usize] {
+        &self.frames
+    }
+}
+
+impl SpriteSheet {
+    /// Read the JSON aseprite exports next to the texture.
+    pub fn from_aseprite(json: &str, texture: Texture2D) -> anyhow::Result<Self> {
+        let (frames, clips) = parse_aseprite(json)?;
+        Ok(Self {
+            texture,
+            frames,
+            clips,
+        })
+    }
+
+    /// Get the frame at the given index.
+    pub fn frame(&self, idx: usize) -> &SheetFrame {
+        &self.frames[idx]
+    }
+
+    /// Get the clip with the given name, if there is one.
+    pub fn clip(&self, name: &str) -> Option<&Clip> {
+        self.clips.get(name)
+    }
+}
+
+/// Get the frames and clips out of aseprite's JSON.
+/// This doesn't need the texture, so it works without a window.
+fn parse_aseprite(json: &str) -> anyhow::Result<(Vec<SheetFrame>, AHashMap<String, Clip>)> {
+    let raw: RawSheet = serde_json::from_str(json)
+        .context("couldn't read the sheet; was it exported with \"Array\" frames?")?;
+
+    let frames: Vec<_> = raw
+        .frames
+        .iter()
+        .map(|frame| SheetFrame {
+            region: Rect::new(frame.frame.x, frame.frame.y, frame.frame.w, frame.frame.h),
+            duration: frame.duration as f32 / 1000.0,
+        })
+        .collect();
+
+    let mut clips = AHashMap::new();
+    for tag in raw.meta.frame_tags {
+        if tag.from > tag.to || tag.to >= frames.len() {
+            bail!(
+                "tag {:?} goes from frame {} to {}, but there are {} frames",
+                tag.name,
+                tag.from,
+                tag.to,
+                frames.len()
+            );
+        }
+        let forward = tag.from..=tag.to;
+        // Ping-pong goes back without doubling up on the frames at the ends
+        let back = (tag.from + 1..tag.to).rev();
+        let order: Vec<usize> = match tag.direction {
+            RawDirection::Forward => forward.collect(),
+            RawDirection::Reverse => forward.rev().collect(),
+            RawDirection::Pingpong => forward.chain(back).collect(),
+            RawDirection::PingpongReverse => forward.rev().chain(back.rev()).collect(),
+        };
+        clips.insert(tag.name, Clip { frames: order });
+    }
+
+    Ok((frames, clips))
+}
+
+// What aseprite puts in the JSON. There's a lot more than this, but this is all we need.
+
+#[derive(Deserialize)]
+struct RawSheet {
+    frames: Vec<RawFrame>,
+    meta: RawMeta,
+}
+
+#[derive(Deserialize)]
+struct RawFrame {
+    frame: RawRect,
+    /// In milliseconds
+    duration: u32,
+}
+
+#[derive(Deserialize)]
+struct RawRect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct RawMeta {
+    #[serde(rename = "frameTags", default)]
+    frame_tags: Vec<RawTag>,
+}
+
+#[derive(Deserialize)]
+struct RawTag {
+    name: String,
+    from: usize,
+    to: usize,
+    direction: RawDirection,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RawDirection {
+    Forward,
+    Reverse,
+    Pingpong,
+    PingpongReverse,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAYER: &str = include_str!("../../assets/textures/sprites/player.json");
+
+    #[test]
+    fn player_sheet_has_its_clips() {
+        let (frames, clips) = parse_aseprite(PLAYER).unwrap();
+        assert_eq!(frames.len(), 17);
+        assert_eq!(clips["idle"].frames(), &[0, 1]);
+        assert_eq!(clips["walk_right"].frames(), &[2, 3, 4, 5]);
+        // Ping-pong doesn't repeat the frames at either end
+        assert_eq!(clips["cast"].frames(), &[14, 15, 16, 15]);
+    }
+
+    #[test]
+    fn tags_past_the_last_frame_are_rejected() {
+        let json = r#"{
+            "frames": [
+                { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 }, "duration": 100 },
+                { "frame": { "x": 16, "y": 0, "w": 16, "h": 16 }, "duration": 100 }
+            ],
+            "meta": {
+                "frameTags": [
+                    { "name": "walk", "from": 1, "to": 2, "direction": "forward" }
+                ]
+            }
+        }"#;
+        assert!(parse_aseprite(json).is_err());
+    }
+}
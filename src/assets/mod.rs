@@ -1,5 +1,8 @@
 #![allow(clippy::eval_order_dependence)]
 
+mod aseprite;
+pub use aseprite::{Clip, SpriteSheet};
+
 use macroquad::{
     audio::{load_sound, Sound},
     miniquad::*,
@@ -27,6 +30,8 @@ impl Assets {
 
 pub struct Textures {
     pub fonts: Fonts,
+    pub tiles: TileAtlases,
+    pub sprites: SpriteSheets,
 
     pub title_banner: Texture2D,
     pub billboard_patch9: Texture2D,
@@ -36,6 +41,8 @@ impl Textures {
     async fn init() -> Self {
         Self {
             fonts: Fonts::init().await,
+            tiles: TileAtlases::init().await,
+            sprites: SpriteSheets::init().await,
             title_banner: texture("title/banner").await,
             billboard_patch9: texture("ui/billboard_patch9").await,
         }
@@ -56,6 +63,52 @@ impl Fonts {
     }
 }
 
+/// Tile textures. Each row is one material, with 4 variants of it across.
+pub struct TileAtlases {
+    pub ground: Texture2D,
+    pub grass_buildings: Texture2D,
+    pub terracotta_grids: Texture2D,
+    /// Outlines drawn over the edges of solid tiles, in white so they can be tinted.
+    /// This is one row of pieces: outer corners, horizontal edges, vertical edges and inner corners.
+    pub edges: Texture2D,
+}
+
+impl TileAtlases {
+    async fn init() -> Self {
+        Self {
+            ground: texture("tiles/ground_atlas").await,
+            grass_buildings: texture("tiles/grass_buildings").await,
+            terracotta_grids: texture("tiles/terracotta_grids").await,
+            edges: texture("tiles/edges").await,
+        }
+    }
+}
+
+/// Which sprite sheet a `Sprite` draws from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteSheetId {
+    Player,
+}
+
+/// Animated sprite sheets, from aseprite.
+pub struct SpriteSheets {
+    pub player: SpriteSheet,
+}
+
+impl SpriteSheets {
+    async fn init() -> Self {
+        Self {
+            player: sprite_sheet("sprites/player").await,
+        }
+    }
+
+    pub fn get(&self, id: SpriteSheetId) -> &SpriteSheet {
+        match id {
+            SpriteSheetId::Player => &self.player,
+        }
+    }
+}
+
 pub struct Sounds {
     pub title_jingle: Sound,
 }
@@ -69,7 +122,16 @@ impl Sounds {
 }
 
 pub struct Shaders {
+    /// Multiplies the scene by the light buffer.
     pub lighting: Material,
+    /// Draws a `LightFalloffKind::Circular` light into the light buffer.
+    pub light_circular: Material,
+    /// Draws a `LightFalloffKind::Cone` light into the light buffer.
+    pub light_cone: Material,
+    /// Multiplies the vertex color into a light buffer, for see-through things.
+    pub light_filter: Material,
+    /// Adds a texture onto the light buffer.
+    pub light_add: Material,
 }
 
 impl Shaders {
@@ -90,7 +152,83 @@ impl Shaders {
             },
         )
         .await;
-        Self { lighting }
+
+        // Lights add up on top of each other
+        let additive = PipelineParams {
+            color_blend: Some(BlendState::new(
+                Equation::Add,
+                BlendFactor::One,
+                BlendFactor::One,
+            )),
+            ..Default::default()
+        };
+        let light_uniforms = || {
+            vec![
+                ("LightPos".to_string(), UniformType::Float2),
+                ("LightColor".to_string(), UniformType::Float3),
+                ("Falloff".to_string(), UniformType::Float1),
+            ]
+        };
+        let light_circular = material_vert_frag(
+            "light",
+            "light_circular",
+            MaterialParams {
+                pipeline_params: additive,
+                uniforms: light_uniforms(),
+                ..Default::default()
+            },
+        )
+        .await;
+        let light_cone = material_vert_frag(
+            "light",
+            "light_cone",
+            MaterialParams {
+                pipeline_params: additive,
+                uniforms: light_uniforms()
+                    .into_iter()
+                    .chain(vec![
+                        ("Spread".to_string(), UniformType::Float1),
+                        ("Facing".to_string(), UniformType::Float1),
+                    ])
+                    .collect(),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let light_filter = material_vert_frag(
+            "standard",
+            "light_filter",
+            MaterialParams {
+                pipeline_params: PipelineParams {
+                    color_blend: Some(BlendState::new(
+                        Equation::Add,
+                        BlendFactor::Zero,
+                        BlendFactor::Value(BlendValue::SourceColor),
+                    )),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await;
+        let light_add = material_vert_frag(
+            "standard",
+            "light_add",
+            MaterialParams {
+                pipeline_params: additive,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        Self {
+            lighting,
+            light_circular,
+            light_cone,
+            light_filter,
+            light_add,
+        }
     }
 }
 
@@ -120,6 +258,22 @@ async fn texture(path: &str) -> Texture2D {
     tex
 }
 
+/// Load a texture and the aseprite JSON next to it.
+async fn sprite_sheet(path: &str) -> SpriteSheet {
+    let tex = texture(path).await;
+    let json = load_string(
+        ASSETS_ROOT
+            .join("textures")
+            .join(path.to_owned() + ".json")
+            .to_string_lossy()
+            .as_ref(),
+    )
+    .await
+    .unwrap();
+    SpriteSheet::from_aseprite(&json, tex)
+        .unwrap_or_else(|e| panic!("couldn't load sprite sheet {}: {:?}", path, e))
+}
+
 async fn sound(path: &str) -> Sound {
     let with_extension = path.to_owned() + ".ogg";
     load_sound(
@@ -1,6 +1,10 @@
 use macroquad::prelude::RenderTarget;
 
-use crate::{assets::Assets, controls::InputSubscriber};
+use crate::{
+    assets::Assets,
+    controls::InputSubscriber,
+    modes::{ModeTransition, TransitionStyle},
+};
 
 /// Things the engine can update and draw
 pub trait Gamemode {
@@ -19,6 +23,22 @@ pub trait Gamemode {
     /// When a `Transition` finishes and things are popped off to reveal this gamemode,
     /// this function is called.
     fn on_resume(&mut self, assets: &Assets) {}
+
+    /// If this returns `true`, the mode underneath this one on the stack is drawn before it,
+    /// so this one can draw on top. Use this for overlays like menus and dialogue boxes.
+    ///
+    /// The stack is drawn upwards from the highest mode that *doesn't* draw below.
+    fn draws_below(&self) -> bool {
+        false
+    }
+
+    /// If this returns `true`, the mode underneath this one on the stack keeps getting updated
+    /// while this is on top of it.
+    ///
+    /// Only the topmost mode gets to change the stack; `Transition`s from lower modes are dropped.
+    fn updates_below(&self) -> bool {
+        false
+    }
 }
 
 /// Information about a frame.
@@ -40,6 +60,9 @@ pub enum Transition {
     None,
     /// Pop the top mode off and replace it with this
     Swap(Box<dyn Gamemode>),
+    /// Pop the top mode off and replace it with this, animating between the two
+    /// over the given number of seconds.
+    SwapAnimated(Box<dyn Gamemode>, TransitionStyle, f32),
     /// Push this mode onto the stack
     Push(Box<dyn Gamemode>),
     /// Pop the top mode off the stack
@@ -60,6 +83,14 @@ impl Transition {
                 }
                 stack.push(new);
             }
+            Transition::SwapAnimated(new, style, duration) => {
+                if let Some(old) = stack.pop() {
+                    stack.push(Box::new(ModeTransition::new(old, new, style, duration)));
+                } else {
+                    // nothing to animate from
+                    stack.push(new);
+                }
+            }
             Transition::Push(new) => {
                 stack.push(new);
             }
@@ -3,18 +3,23 @@ use enum_map::Enum;
 use macroquad::{
     miniquad::{self, Context, KeyMods},
     prelude::{
-        mouse_position, screen_height, screen_width,
+        mouse_position,
         utils::{register_input_subscriber, repeat_all_miniquad_input},
         vec2, DVec2, KeyCode, MouseButton, Vec2,
     },
 };
 
+use strum_macros::EnumIter;
+
 use std::collections::HashMap;
 
-use crate::{utils::draw::width_height_deficit, HEIGHT, WIDTH};
+use crate::utils::{
+    coords::{screen_to_canvas, ViewTransform},
+    settings::Settings,
+};
 
 /// The controls
-#[derive(Enum, Copy, Clone)]
+#[derive(Enum, Copy, Clone, PartialEq, Eq, Debug, EnumIter)]
 pub enum Control {
     Click,
     Up,
@@ -23,21 +28,45 @@ pub enum Control {
     Right,
 
     Submit,
+    Pause,
     Debug,
+
+    /// Switch the player's light between glowing all around and beaming at the mouse.
+    Lantern,
 }
 
 /// Combo keycode and mouse button code
-#[derive(Hash, PartialEq, Eq, Copy, Clone)]
+#[derive(Hash, PartialEq, Eq, Copy, Clone, Debug)]
 pub enum InputCode {
     Key(KeyCode),
     Mouse(MouseButton),
 }
 
+impl InputCode {
+    /// Human-readable name for showing in menus.
+    pub fn name(&self) -> String {
+        match self {
+            InputCode::Key(key) => format!("{:?}", key),
+            InputCode::Mouse(button) => format!("Mouse {:?}", button),
+        }
+    }
+}
+
 /// Event handler to hook into miniquad and get inputs
 #[derive(Clone)]
 pub struct InputSubscriber {
     controls: EventInputHandler<InputCode, Control>,
     subscriber_id: usize,
+
+    /// The settings revision the keybinds were last loaded from.
+    settings_revision: u64,
+    /// The last raw input pressed this frame, mapped or not.
+    /// Used for rebinding controls.
+    last_input: Option<InputCode>,
+    /// Text typed (or pasted) this frame.
+    typed: String,
+    /// How many times backspace was pressed this frame, counting key repeats.
+    backspaces: usize,
 }
 
 impl InputSubscriber {
@@ -45,9 +74,14 @@ impl InputSubscriber {
         // the science kid
         let sid = register_input_subscriber();
 
+        let settings = Settings::get();
         InputSubscriber {
-            controls: EventInputHandler::new(Self::default_controls()),
+            controls: EventInputHandler::new(settings.keybinds.clone()),
             subscriber_id: sid,
+            settings_revision: settings.revision(),
+            last_input: None,
+            typed: String::new(),
+            backspaces: 0,
         }
     }
 
@@ -64,7 +98,9 @@ impl InputSubscriber {
             (D, Control::Right),
             //
             (Enter, Control::Submit),
+            (Escape, Control::Pause),
             (Backslash, Control::Debug),
+            (F, Control::Lantern),
         ] {
             controls.insert(InputCode::Key(key), ctrl);
         }
@@ -73,10 +109,36 @@ impl InputSubscriber {
     }
 
     pub fn update(&mut self) {
+        // Pick up any keybinds changed in the options menu
+        let settings = Settings::get();
+        if settings.revision() != self.settings_revision {
+            self.controls = EventInputHandler::new(settings.keybinds.clone());
+            self.settings_revision = settings.revision();
+        }
+        drop(settings);
+
+        self.last_input = None;
+        self.typed.clear();
+        self.backspaces = 0;
         repeat_all_miniquad_input(self, self.subscriber_id);
         self.controls.update();
     }
 
+    /// The last raw input pressed this frame, whether or not it's bound to anything.
+    pub fn last_input(&self) -> Option<InputCode> {
+        self.last_input
+    }
+
+    /// Text typed this frame, including anything pasted with Ctrl+V.
+    pub fn typed(&self) -> &str {
+        &self.typed
+    }
+
+    /// How many characters the player wants to erase this frame.
+    pub fn backspaces(&self) -> usize {
+        self.backspaces
+    }
+
     /// Normalized vector indicating the direction the player is inputting
     pub fn pressed_vec(&self) -> Vec2 {
         let mut out = Vec2::ZERO;
@@ -97,16 +159,19 @@ impl InputSubscriber {
         out.normalize_or_zero()
     }
 
-    /// Returns where the mouse is in pixel coordinates.
+    /// Returns where the mouse is in canvas pixel coordinates.
     ///
     /// Although this can totally be gotten without the input handler,
     /// it just feels wrong to have it be a free-floating function...
     pub fn mouse_pos(&self) -> Vec2 {
         let (mx, my) = mouse_position();
-        let (wd, hd) = width_height_deficit();
-        let mx = (mx - wd / 2.0) / ((screen_width() - wd) / WIDTH);
-        let my = (my - hd / 2.0) / ((screen_height() - hd) / HEIGHT);
-        vec2(mx, my)
+        screen_to_canvas(vec2(mx, my))
+    }
+
+    /// Returns where the mouse is in the world, looking through the given transform.
+    pub fn mouse_world_pos(&self, transform: &ViewTransform) -> Vec2 {
+        let (mx, my) = mouse_position();
+        transform.screen_to_world(vec2(mx, my))
     }
 }
 
@@ -125,13 +190,32 @@ impl miniquad::EventHandler for InputSubscriber {
 
     fn key_down_event(
         &mut self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         keycode: KeyCode,
-        _keymods: KeyMods,
+        keymods: KeyMods,
         repeat: bool,
     ) {
         if !repeat {
             self.controls.input_down(InputCode::Key(keycode));
+            self.last_input = Some(InputCode::Key(keycode));
+        }
+
+        match keycode {
+            KeyCode::Backspace => self.backspaces += 1,
+            KeyCode::V if (keymods.ctrl || keymods.logo) && !repeat => {
+                if let Some(pasted) = ctx.clipboard_get() {
+                    self.typed
+                        .extend(pasted.chars().filter(|c| !c.is_control()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn char_event(&mut self, _ctx: &mut Context, character: char, keymods: KeyMods, _repeat: bool) {
+        // Ctrl+V and friends shouldn't type anything
+        if !character.is_control() && !keymods.ctrl && !keymods.logo {
+            self.typed.push(character);
         }
     }
 
@@ -147,6 +231,7 @@ impl miniquad::EventHandler for InputSubscriber {
         _y: f32,
     ) {
         self.controls.input_down(InputCode::Mouse(button));
+        self.last_input = Some(InputCode::Mouse(button));
     }
     fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, _x: f32, _y: f32) {
         self.controls.input_up(InputCode::Mouse(button));
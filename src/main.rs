@@ -16,7 +16,7 @@ use crate::{
     assets::Assets,
     boilerplates::{FrameInfo, Gamemode},
     controls::InputSubscriber,
-    modes::ModeLogo,
+    modes::{run_map_tool, ModeLogo},
     utils::draw::width_height_deficit,
 };
 
@@ -29,7 +29,7 @@ const ASPECT_RATIO: f32 = WIDTH / HEIGHT;
 const UPDATES_PER_DRAW: u64 = 1;
 const UPDATE_DT: f32 = 1.0 / (30.0 * UPDATES_PER_DRAW as f32);
 
-/// The `macroquad::main` macro uses this.
+/// `main` opens the window with this, like the `macroquad::main` macro would.
 fn window_conf() -> Conf {
     Conf {
         window_title: if cfg!(debug_assertions) {
@@ -44,8 +44,17 @@ fn window_conf() -> Conf {
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
+fn main() {
+    // Map tools run from the command line and don't need a window
+    let args = std::env::args().collect::<Vec<_>>();
+    if let Some(code) = run_map_tool(&args) {
+        std::process::exit(code);
+    }
+
+    macroquad::Window::from_config(window_conf(), amain());
+}
+
+async fn amain() {
     let assets = Assets::init().await;
     let assets = Box::leak(Box::new(assets)) as &'static Assets;
     let mut controls = InputSubscriber::new();
@@ -69,10 +78,18 @@ async fn main() {
         for _ in 0..UPDATES_PER_DRAW {
             controls.update();
 
-            let transition = mode_stack
-                .last_mut()
-                .unwrap()
-                .update(&controls, frame_info, assets);
+            // Update any modes underneath that asked to keep running
+            let update_from = mode_stack
+                .iter()
+                .rposition(|mode| !mode.updates_below())
+                .unwrap_or(0);
+            let (top, below) = mode_stack.split_last_mut().unwrap();
+            for mode in below[update_from.min(below.len())..].iter_mut() {
+                // Only the top gets to change the stack
+                let _ = mode.update(&controls, frame_info, assets);
+            }
+
+            let transition = top.update(&controls, frame_info, assets);
             transition.apply(&mut mode_stack, assets);
         }
 
@@ -88,9 +105,14 @@ async fn main() {
             ..Default::default()
         });
         clear_background(WHITE);
-        // Draw the state.
-        let drawer = mode_stack.last_mut().unwrap();
-        drawer.draw(assets, frame_info, &controls);
+        // Draw the state, and anything under it that shows through.
+        let draw_from = mode_stack
+            .iter()
+            .rposition(|mode| !mode.draws_below())
+            .unwrap_or(0);
+        for drawer in &mode_stack[draw_from..] {
+            drawer.draw(assets, frame_info, &controls);
+        }
 
         // Done rendering to the canvas; go back to our normal camera
         // to size the canvas
@@ -2,18 +2,24 @@ use crate::{
     assets::Assets,
     boilerplates::{FrameInfo, Gamemode, Transition},
     controls::{Control, InputSubscriber},
-    utils::draw::{self, hexcolor},
+    utils::{
+        draw::{self, hexcolor},
+        settings::Settings,
+    },
     HEIGHT, WIDTH,
 };
 
 use cogs_gamedev::{chance::WeightedPicker, controls::InputHandler};
-use macroquad::prelude::{Color, RenderTarget};
+use macroquad::{
+    audio::PlaySoundParams,
+    prelude::{Color, RenderTarget},
+};
 use quad_rand::compat::QuadRand;
 use rand::Rng;
 
 use std::f32::consts::TAU;
 
-use super::ModeOverworld;
+use super::{ModeNewGame, TransitionStyle};
 
 const BANNER_DISPLAY_SIZE: f32 = WIDTH * 0.6;
 const BANNER_START_TIME: f64 = 0.25;
@@ -88,7 +94,13 @@ impl Gamemode for ModeLogo {
         if self.first_frame {
             self.first_frame = false;
             self.start_time = macroquad::time::get_time();
-            macroquad::audio::play_sound_once(assets.sounds.title_jingle);
+            macroquad::audio::play_sound(
+                assets.sounds.title_jingle,
+                PlaySoundParams {
+                    looped: false,
+                    volume: Settings::get().master_volume,
+                },
+            );
         }
 
         if macroquad::time::get_time() - self.start_time > 5.0
@@ -97,7 +109,11 @@ impl Gamemode for ModeLogo {
             macroquad::audio::stop_sound(assets.sounds.title_jingle);
 
             // Put your next state here!
-            Transition::Swap(Box::new(ModeOverworld::init()))
+            Transition::SwapAnimated(
+                Box::new(ModeNewGame::new()),
+                TransitionStyle::FadeThroughBlack,
+                1.0,
+            )
         } else {
             Transition::None
         }
@@ -1,4 +1,10 @@
 mod logo;
 pub use logo::ModeLogo;
+mod newgame;
+pub use newgame::ModeNewGame;
 mod overworld;
-pub use overworld::ModeOverworld;
+pub use overworld::{run_map_tool, ModeLoading, ModeOverworld};
+mod pause;
+pub use pause::ModePause;
+mod transition;
+pub use transition::{canvas_target, draw_into_target, ModeTransition, TransitionStyle};
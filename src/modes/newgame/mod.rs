@@ -0,0 +1,211 @@
+use crate::{
+    assets::Assets,
+    boilerplates::{FrameInfo, Gamemode, Transition},
+    controls::{Control, InputSubscriber},
+    utils::{
+        draw::hexcolor,
+        text::{Billboard, Markup, TextSpan},
+    },
+    HEIGHT, WIDTH,
+};
+
+use cogs_gamedev::controls::InputHandler;
+use macroquad::prelude::{vec2, Color, WHITE};
+use quad_rand::compat::QuadRand;
+use rand::Rng;
+
+use super::{ModeLoading, TransitionStyle};
+
+/// Width of the menu in patch9 tiles
+const MENU_WIDTH: usize = 10;
+/// Longest seed you can type in
+const MAX_SEED_LEN: usize = 24;
+
+/// Mode for picking the seed before starting a new game.
+///
+/// You can type or paste one in, or roll a random one.
+/// Seeds that aren't numbers get hashed, so you can share words too.
+pub struct ModeNewGame {
+    /// What's in the seed box.
+    seed_text: String,
+    /// Index of the highlighted entry.
+    cursor: usize,
+    /// Whether typing goes into the seed box right now.
+    editing: bool,
+}
+
+/// Things you can click on in the menu
+#[derive(Debug, Clone, Copy)]
+enum Entry {
+    Seed,
+    Random,
+    Start,
+}
+
+const ENTRIES: [Entry; 3] = [Entry::Seed, Entry::Random, Entry::Start];
+
+impl ModeNewGame {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            seed_text: random_seed_text(),
+            cursor: ENTRIES.len() - 1,
+            editing: false,
+        }
+    }
+
+    fn label(&self, entry: Entry, frame_info: FrameInfo) -> String {
+        match entry {
+            Entry::Seed => {
+                // Blink a cursor while typing
+                let caret = if self.editing && frame_info.frames_ran / 15 % 2 == 0 {
+                    "_"
+                } else {
+                    ""
+                };
+                format!("Seed: {}{}", self.seed_text, caret)
+            }
+            Entry::Random => "Random seed".to_owned(),
+            Entry::Start => "Start".to_owned(),
+        }
+    }
+
+    /// Make the billboard the menu is drawn on.
+    ///
+    /// Span 0 is the title; span `n + 1` is entry `n`.
+    fn billboard(&self, assets: &Assets, frame_info: FrameInfo) -> Billboard {
+        let font = assets.textures.fonts.small;
+        let markup = |color| Markup {
+            font,
+            color,
+            kerning: 1.0,
+            vert_space: 3.0,
+            wave: None,
+        };
+
+        let mut text = vec![TextSpan::new(
+            "NEW GAME\n".to_owned(),
+            markup(Color::new(1.0, 0.9, 0.6, 1.0)),
+        )];
+        for (idx, entry) in ENTRIES.iter().enumerate() {
+            let (prefix, color) = if idx == self.cursor {
+                ("> ", WHITE)
+            } else {
+                ("  ", Color::new(0.7, 0.7, 0.7, 1.0))
+            };
+            // Pad it out so you can click anywhere on the line
+            let label = format!("{}{:<30}\n", prefix, self.label(*entry, frame_info));
+            text.push(TextSpan::new(label, markup(color)));
+        }
+
+        let height = (text.len() * 8) / 16 + 2;
+        let tile_size = assets.textures.billboard_patch9.width() / 3.0;
+        Billboard::new(
+            text,
+            vec2(
+                WIDTH / 2.0 - MENU_WIDTH as f32 * tile_size / 2.0,
+                HEIGHT / 2.0 - height as f32 * tile_size / 2.0,
+            ),
+            vec2(8.0, 14.0),
+            assets.textures.billboard_patch9,
+            MENU_WIDTH,
+            height,
+        )
+    }
+
+    /// Do the thing the entry says to.
+    fn activate(&mut self, entry: Entry) -> Transition {
+        match entry {
+            Entry::Seed => self.editing = true,
+            Entry::Random => self.seed_text = random_seed_text(),
+            Entry::Start => {
+                if self.seed_text.trim().is_empty() {
+                    self.seed_text = random_seed_text();
+                }
+                return Transition::SwapAnimated(
+                    Box::new(ModeLoading::new(parse_seed(&self.seed_text))),
+                    TransitionStyle::FadeThroughBlack,
+                    1.0,
+                );
+            }
+        }
+        Transition::None
+    }
+}
+
+impl Gamemode for ModeNewGame {
+    fn update(
+        &mut self,
+        controls: &InputSubscriber,
+        frame_info: FrameInfo,
+        assets: &Assets,
+    ) -> Transition {
+        if self.editing {
+            for _ in 0..controls.backspaces() {
+                self.seed_text.pop();
+            }
+            for c in controls.typed().chars() {
+                if self.seed_text.chars().count() < MAX_SEED_LEN {
+                    self.seed_text.push(c);
+                }
+            }
+            if controls.clicked_down(Control::Submit)
+                || controls.clicked_down(Control::Pause)
+                || controls.clicked_down(Control::Click)
+            {
+                self.editing = false;
+            }
+            return Transition::None;
+        }
+
+        if controls.clicked_down(Control::Up) {
+            self.cursor = (self.cursor + ENTRIES.len() - 1) % ENTRIES.len();
+        }
+        if controls.clicked_down(Control::Down) {
+            self.cursor = (self.cursor + 1) % ENTRIES.len();
+        }
+
+        if controls.clicked_down(Control::Submit) {
+            return self.activate(ENTRIES[self.cursor]);
+        }
+        if controls.clicked_down(Control::Click) {
+            let clicked = self
+                .billboard(assets, frame_info)
+                .get_char_at_pixel(controls.mouse_pos(), 1.0);
+            if let Some((span_idx, _, _)) = clicked {
+                if span_idx >= 1 {
+                    self.cursor = span_idx - 1;
+                    return self.activate(ENTRIES[self.cursor]);
+                }
+            }
+        }
+
+        Transition::None
+    }
+
+    fn draw(&self, assets: &Assets, frame_info: FrameInfo, _controls: &InputSubscriber) {
+        use macroquad::prelude::*;
+
+        clear_background(hexcolor(0x21181bff));
+
+        self.billboard(assets, frame_info).draw();
+    }
+}
+
+/// Turn whatever's in the seed box into a seed.
+///
+/// Numbers are used as-is, so a seed shown in the pause menu can be typed back in.
+/// Anything else gets hashed.
+pub fn parse_seed(text: &str) -> u64 {
+    let text = text.trim();
+    text.parse().unwrap_or_else(|_| {
+        // FNV-1a: it's tiny and it'll never change out from under us
+        text.bytes().fold(0xcbf29ce484222325, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        })
+    })
+}
+
+fn random_seed_text() -> String {
+    QuadRand.gen::<u64>().to_string()
+}
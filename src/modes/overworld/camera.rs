@@ -0,0 +1,243 @@
+//! Where the overworld is looked at from.
+
+use hecs::{Entity, World};
+use macroquad::prelude::{vec2, Rect, Vec2};
+use quad_rand::compat::QuadRand;
+use rand::Rng;
+
+use crate::{utils::coords::ViewTransform, HEIGHT, WIDTH};
+
+use super::{
+    cs::{
+        explosions::Explosion,
+        physics::{HasCollider, HasRigidBody},
+    },
+    physics::PhysicsWorld,
+    WorldExt,
+};
+
+/// How much the player's velocity factors into the camera pos
+const PLAYER_VEL_CAMERA_INFLUENCE: f32 = 2.2;
+/// How much of the way to the target pos the camera pos tries to go
+/// (factoring in the dt)
+const CAMERA_SNAPPINESS: f32 = 0.8;
+/// How far away the camera's position must be away from the target before
+/// we move it
+const CAMERA_TOLERANCE: f32 = 1.5;
+/// If the camera is *too* far away, bring it back into this distance.
+const CAMERA_MAX_DIST: f32 = 16.0;
+/// How much of the way to the target zoom the zoom goes each second.
+const ZOOM_SNAPPINESS: f32 = 4.0;
+
+/// How much trauma wears off each second.
+const TRAUMA_DECAY: f32 = 1.5;
+/// Furthest the camera gets pushed by shaking, in world units.
+const MAX_SHAKE: f32 = 0.75;
+/// An explosion this strong right on top of the camera shakes it as hard as it goes.
+const FULL_SHAKE_STRENGTH: f32 = 1000.0;
+/// Explosions this far away from the camera don't shake it at all.
+const SHAKE_RANGE: f32 = 24.0;
+
+/// How many screen pixels there are to one world unit when the zoom is 1.
+const PIXELS_PER_UNIT: f32 = 16.0;
+
+/// What the camera follows around.
+#[derive(Debug, Clone, Copy)]
+pub enum CameraFocus {
+    /// The player, leading a little ahead of where they're going.
+    Player,
+    /// Something else, like a spell or a shrine.
+    /// If it goes away, the camera goes back to the player.
+    Entity(Entity),
+}
+
+/// The overworld camera.
+///
+/// It eases over towards whatever it's focused on, shakes when things explode,
+/// zooms smoothly, and doesn't look past the edges of the level.
+pub struct Camera {
+    /// Place where the camera is, not counting shake
+    pos: Vec2,
+    /// Place where the camera targets
+    target: Vec2,
+    focus: CameraFocus,
+
+    /// How zoomed in we are; 2 is twice as big as normal.
+    zoom: f32,
+    /// How zoomed in we're trying to be.
+    zoom_target: f32,
+
+    /// How shaken up the camera is, from 0 to 1.
+    /// The shake goes up with the square of this so small bumps stay small.
+    trauma: f32,
+    /// How far the shake is pushing the camera this frame.
+    shake_offset: Vec2,
+
+    /// The camera won't look outside of this, if it's set.
+    bounds: Option<Rect>,
+}
+
+impl Camera {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            pos: Vec2::ZERO,
+            target: Vec2::ZERO,
+            focus: CameraFocus::Player,
+            zoom: 1.0,
+            zoom_target: 1.0,
+            trauma: 0.0,
+            shake_offset: Vec2::ZERO,
+            bounds: None,
+        }
+    }
+
+    /// Place where the camera is, not counting shake.
+    pub fn pos(&self) -> Vec2 {
+        self.pos
+    }
+
+    /// Place the camera is heading to.
+    pub fn target(&self) -> Vec2 {
+        self.target
+    }
+
+    /// Follow something else around.
+    pub fn set_focus(&mut self, focus: CameraFocus) {
+        self.focus = focus;
+    }
+
+    /// Start smoothly zooming to the given zoom; 2 is twice as big as normal.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom_target = zoom;
+    }
+
+    /// Keep the camera from looking outside of `bounds`, or stop keeping it in with None.
+    pub fn set_bounds(&mut self, bounds: Option<Rect>) {
+        self.bounds = bounds;
+    }
+
+    /// Shake the camera. 1 is as hard as it goes.
+    pub fn shake(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.0);
+    }
+
+    /// Move the camera along.
+    pub fn update(&mut self, world: &World, physics: &PhysicsWorld, dt: f32) {
+        if let Some(target) = self.find_target(world, physics) {
+            self.target = target;
+        }
+        self.target = self.clamp_to_bounds(self.target, self.zoom_target);
+
+        let cam_delta = self.target - self.pos;
+        if cam_delta.length_squared() > CAMERA_TOLERANCE * CAMERA_TOLERANCE {
+            self.pos += cam_delta * CAMERA_SNAPPINESS * dt;
+        }
+        // Don't let the target run off the screen
+        let cam_delta = self.target - self.pos;
+        if cam_delta.length_squared() > CAMERA_MAX_DIST * CAMERA_MAX_DIST {
+            self.pos = self.target - cam_delta / cam_delta.length() * CAMERA_MAX_DIST;
+        }
+
+        let zoom_t = 1.0 - (-ZOOM_SNAPPINESS * dt).exp();
+        self.zoom += (self.zoom_target - self.zoom) * zoom_t;
+        self.pos = self.clamp_to_bounds(self.pos, self.zoom);
+
+        self.trauma = (self.trauma - TRAUMA_DECAY * dt).max(0.0);
+        let shake = self.trauma * self.trauma * MAX_SHAKE;
+        self.shake_offset = vec2(
+            QuadRand.gen_range(-1.0..1.0) * shake,
+            QuadRand.gen_range(-1.0..1.0) * shake,
+        );
+    }
+
+    /// Jump right to the target and zoom, without easing over or shaking.
+    pub fn snap(&mut self, world: &World, physics: &PhysicsWorld) {
+        if let Some(target) = self.find_target(world, physics) {
+            self.target = target;
+        }
+        self.zoom = self.zoom_target;
+        self.target = self.clamp_to_bounds(self.target, self.zoom);
+        self.pos = self.target;
+        self.trauma = 0.0;
+        self.shake_offset = Vec2::ZERO;
+    }
+
+    /// The part of the world the camera can see, in world units.
+    pub fn view(&self) -> Rect {
+        let size = view_size(self.zoom);
+        // Round the camera pos to the nearest pixel to prevent driftiness
+        let center = self.pos + self.shake_offset;
+        let cam_x = (center.x * PIXELS_PER_UNIT).round() / PIXELS_PER_UNIT;
+        let cam_y = (center.y * PIXELS_PER_UNIT).round() / PIXELS_PER_UNIT;
+        Rect::new(cam_x - size.x / 2.0, cam_y - size.y / 2.0, size.x, size.y)
+    }
+
+    /// Get the transform between the canvas and the part of the world the camera can see.
+    pub fn transform(&self) -> ViewTransform {
+        ViewTransform::new(self.view())
+    }
+
+    /// Work out where the focus is. If it's gone, go back to following the player.
+    fn find_target(&mut self, world: &World, physics: &PhysicsWorld) -> Option<Vec2> {
+        if let CameraFocus::Entity(e) = self.focus {
+            if let Ok(coll_h) = world.get::<HasCollider>(e) {
+                let coll = physics.colliders.get(coll_h.0).unwrap();
+                return Some(coll.compute_aabb().center().into());
+            }
+            self.focus = CameraFocus::Player;
+        }
+
+        // Use the body, not the collider, because the collider doesn't catch up
+        // with a teleport until the next physics step
+        let player_h = world.get_player()?;
+        let rb_h = world.get::<HasRigidBody>(player_h).unwrap();
+        let rb = physics.rigid_bodies.get(rb_h.0).unwrap();
+
+        let pos = rb.translation();
+        let vel = rb.linvel();
+        Some((pos + vel * PLAYER_VEL_CAMERA_INFLUENCE).into())
+    }
+
+    /// Move `pos` so a camera there with the given zoom doesn't see outside the bounds.
+    ///
+    /// If the bounds are smaller than the view, it goes in the middle.
+    fn clamp_to_bounds(&self, pos: Vec2, zoom: f32) -> Vec2 {
+        let bounds = match self.bounds {
+            Some(it) => it,
+            None => return pos,
+        };
+        let half = view_size(zoom) / 2.0;
+        // These are the furthest the middle of the view can go
+        let clamp_axis = |pos: f32, min: f32, max: f32| {
+            if max < min {
+                (min + max) / 2.0
+            } else {
+                pos.clamp(min, max)
+            }
+        };
+        vec2(
+            clamp_axis(pos.x, bounds.x + half.x, bounds.right() - half.x),
+            clamp_axis(pos.y, bounds.y + half.y, bounds.bottom() - half.y),
+        )
+    }
+}
+
+/// Shake the camera for every explosion going off this frame, less for the far away ones.
+///
+/// Run this after the physics and before the explosions get cleaned up.
+pub fn system_camera_shake(world: &World, physics: &PhysicsWorld, camera: &mut Camera) {
+    for (_, (explosion, coll_h)) in world.query::<(&Explosion, &HasCollider)>().iter() {
+        let coll = physics.colliders.get(coll_h.0).unwrap();
+        let pos: Vec2 = coll.compute_aabb().center().into();
+        let closeness = 1.0 - (pos - camera.pos).length() / SHAKE_RANGE;
+        if closeness > 0.0 {
+            camera.shake((explosion.strength() / FULL_SHAKE_STRENGTH).min(1.0) * closeness);
+        }
+    }
+}
+
+/// How much of the world you can see at the given zoom, in world units.
+fn view_size(zoom: f32) -> Vec2 {
+    vec2(WIDTH, HEIGHT) / (PIXELS_PER_UNIT * zoom)
+}
@@ -3,7 +3,7 @@ use std::f32::consts::TAU;
 use hecs::{Entity, World};
 use macroquad::{
     color::{hsl_to_rgb, rgb_to_hsl},
-    prelude::{info, vec2, Color},
+    prelude::{info, vec2, vec3, Color, Vec3},
 };
 use macroquad_particles::{ColorCurve, EmissionShape, EmitterConfig, ParticleShape};
 use rapier2d::prelude::*;
@@ -11,6 +11,8 @@ use rapier2d::prelude::*;
 use crate::modes::overworld::{cs::dazing::Dazeable, physics::PhysicsWorld, WorldExt};
 
 use super::{
+    light::{Illuminator, LightAnimation, LightFalloffKind, LightFilter},
+    limited_time_offer::LimitedTimeOffer,
     particles::ParticleEmitter,
     physics::{HasCollider, HasRigidBody},
 };
@@ -45,6 +47,11 @@ impl Explosion {
 
         world.spawn_with_physics(physics, (Explosion { strength, color },), collider, None)
     }
+
+    /// Get how strong the explosion is.
+    pub fn strength(&self) -> f32 {
+        self.strength
+    }
 }
 
 /// Call this when an explosion intersects with something else with a rigidbody.
@@ -72,7 +79,10 @@ pub fn handler_explosion(
     }
 }
 
-/// System that despawns explosions and adds a particle emitter.
+/// How long the flash of light from an explosion lasts, in seconds.
+const FLASH_TIME: f32 = 0.5;
+
+/// System that despawns explosions and adds a particle emitter and a flash of light.
 /// Run this before any explosions are created.
 pub fn system_cleanup_explosions(world: &mut World, physics: &mut PhysicsWorld) {
     let config = EmitterConfig {
@@ -118,19 +128,47 @@ pub fn system_cleanup_explosions(world: &mut World, physics: &mut PhysicsWorld)
             ..config.clone()
         };
 
+        // The smoke dims light going through it, tinted a little like the explosion
+        let smoke = LightFilter::new(vec3(color.r, color.g, color.b) * 0.3 + Vec3::splat(0.4));
+
+        // Bright enough to see the whole room for a moment, flickering away as the fire dies down
+        let flash = Illuminator::new(
+            vec3(color.r, color.g, color.b) * 1.5,
+            LightFalloffKind::Circular { m: 0.08 },
+        )
+        .with_animation(LightAnimation {
+            flicker: 0.3,
+            flicker_speed: 20.0,
+            fade_out: FLASH_TIME,
+            ..Default::default()
+        });
+
         add.push((
             ParticleEmitter::new(config, true),
+            smoke,
             ColliderBuilder::ball(1.0)
                 .translation(center.coords)
                 .sensor(true)
                 .build(),
+            flash,
+            ColliderBuilder::ball(0.1)
+                .translation(center.coords)
+                .sensor(true)
+                .collision_groups(InteractionGroups::none())
+                .build(),
         ));
 
         remove.push(e);
     }
 
-    for (emitter, collider) in add {
-        world.spawn_with_physics(physics, (emitter,), collider, None);
+    for (emitter, smoke, collider, flash, flash_collider) in add {
+        world.spawn_with_physics(physics, (emitter, smoke), collider, None);
+        world.spawn_with_physics(
+            physics,
+            (flash, LimitedTimeOffer::new(FLASH_TIME)),
+            flash_collider,
+            None,
+        );
     }
 
     for e in remove {
@@ -0,0 +1,56 @@
+use hecs::World;
+
+use crate::modes::overworld::{cs::light::Illuminator, physics::PhysicsWorld};
+
+use super::physics::HasRigidBody;
+
+/// Below this speed, things facing along their velocity keep facing where they were.
+const MIN_FACING_SPEED: f32 = 0.01;
+
+/// Component for things that point somewhere.
+///
+/// Cone lights on the same entity point the same way.
+pub struct Facing {
+    /// Angle we're pointing in, in radians.
+    angle: f32,
+    /// Where the angle comes from.
+    from: FacingFrom,
+}
+
+pub enum FacingFrom {
+    /// Only changes when something sets it, like the player aiming with the mouse.
+    Fixed,
+    /// Points the way the rigid body is moving.
+    Velocity,
+}
+
+impl Facing {
+    pub fn new(angle: f32, from: FacingFrom) -> Self {
+        Self { angle, from }
+    }
+
+    /// Point somewhere else.
+    pub fn set_angle(&mut self, angle: f32) {
+        self.angle = angle;
+    }
+}
+
+/// Turn things to face wherever they're supposed to, and point their lights that way.
+pub fn system_update_facing(world: &mut World, physics: &PhysicsWorld) {
+    for (_, (facing, rb_handle)) in world.query_mut::<(&mut Facing, Option<&HasRigidBody>)>() {
+        match (&facing.from, rb_handle) {
+            (FacingFrom::Velocity, Some(rb_handle)) => {
+                let rb = physics.rigid_bodies.get(rb_handle.0).unwrap();
+                let vel = rb.linvel();
+                if vel.magnitude() > MIN_FACING_SPEED {
+                    facing.angle = vel.y.atan2(vel.x);
+                }
+            }
+            (FacingFrom::Velocity, None) | (FacingFrom::Fixed, _) => {}
+        }
+    }
+
+    for (_, (facing, light)) in world.query_mut::<(&Facing, &mut Illuminator)>() {
+        light.set_facing(facing.angle);
+    }
+}
@@ -1,10 +1,105 @@
+use std::f32::consts::TAU;
+
+use hecs::{Entity, World};
 use macroquad::prelude::*;
+use nalgebra::Point2;
+use quad_rand::compat::QuadRand;
+use rand::Rng;
+use rapier2d::{parry::bounding_volume::AABB, prelude::InteractionGroups};
+
+use crate::{
+    assets::Assets,
+    modes::overworld::{
+        cs::{limited_time_offer::LimitedTimeOffer, physics::HasCollider},
+        layers::{RenderLayer, RenderLayers},
+        physics::{collider_groups, PhysicsWorld},
+        visibility::{shadow_of, VisibilityPolygon},
+    },
+};
+
+/// No light reaches further than this, no matter how gently it falls off.
+const MAX_LIGHT_RADIUS: f32 = 32.0;
 
 /// Component for things that emit light.
 pub struct Illuminator {
     /// Base light color (as a vec3)
     color: Vec3,
     kind: LightFalloffKind,
+    animation: LightAnimation,
+    /// How long the light has been animating for.
+    age: f32,
+    /// Where in the flicker noise this light starts, so lights don't all flicker together.
+    flicker_offset: f32,
+    /// What the color gets multiplied by this frame, worked out from the animation.
+    brightness: f32,
+}
+
+/// How a light's brightness changes over time.
+///
+/// All the effects multiply together; the default is a light that never changes.
+#[derive(Debug, Clone)]
+pub struct LightAnimation {
+    /// How much the brightness randomly dips, from 0 (not at all) to 1 (all the way to dark).
+    pub flicker: f32,
+    /// How many times a second the flicker picks a new brightness to drift to.
+    pub flicker_speed: f32,
+    /// How much the brightness dips at the bottom of each pulse, from 0 to 1.
+    pub pulse: f32,
+    /// How long one pulse takes, in seconds.
+    pub pulse_period: f32,
+    /// How long it takes to fade in from dark when the light appears, in seconds.
+    pub fade_in: f32,
+    /// If the entity has a `LimitedTimeOffer`, start fading out to dark when there's this much time left.
+    pub fade_out: f32,
+}
+
+impl Default for LightAnimation {
+    fn default() -> Self {
+        Self {
+            flicker: 0.0,
+            flicker_speed: 10.0,
+            pulse: 0.0,
+            pulse_period: 1.0,
+            fade_in: 0.0,
+            fade_out: 0.0,
+        }
+    }
+}
+
+impl LightAnimation {
+    /// How bright the light is at this age, if it has `time_left` before it goes away.
+    fn brightness(&self, age: f32, flicker_offset: f32, time_left: Option<f32>) -> f32 {
+        let mut brightness = 1.0;
+        if self.flicker > 0.0 {
+            brightness *=
+                1.0 - self.flicker * value_noise(age * self.flicker_speed + flicker_offset);
+        }
+        if self.pulse > 0.0 && self.pulse_period > 0.0 {
+            let wave = 0.5 - 0.5 * (age / self.pulse_period * TAU).cos();
+            brightness *= 1.0 - self.pulse * wave;
+        }
+        if self.fade_in > 0.0 {
+            brightness *= (age / self.fade_in).min(1.0);
+        }
+        if let Some(time_left) = time_left.filter(|_| self.fade_out > 0.0) {
+            brightness *= (time_left / self.fade_out).clamp(0.0, 1.0);
+        }
+        brightness
+    }
+}
+
+/// Component for see-through things that light passes through, like smoke.
+///
+/// The light that goes through gets multiplied by `tint`, so white lets everything through
+/// and black blocks it all. Anything in `GROUP_LIGHTING` without one of these blocks light completely.
+pub struct LightFilter {
+    tint: Vec3,
+}
+
+impl LightFilter {
+    pub fn new(tint: Vec3) -> Self {
+        Self { tint }
+    }
 }
 
 /// Stealing this entirely from Upper Crust
@@ -18,34 +113,257 @@ pub enum LightFalloffKind {
 
 impl Illuminator {
     pub fn new(color: Vec3, kind: LightFalloffKind) -> Self {
-        Self { color, kind }
+        Self {
+            color,
+            kind,
+            animation: LightAnimation::default(),
+            age: 0.0,
+            flicker_offset: QuadRand.gen_range(0.0..1000.0),
+            brightness: 1.0,
+        }
     }
 
-    /// Given the position of this light and the target position in the world,
-    /// get the amount of light that this contributes to that target.
-    ///
-    /// (As a RGB vector.)
+    /// Make the light's brightness change over time.
+    pub fn with_animation(mut self, animation: LightAnimation) -> Self {
+        self.brightness = animation.brightness(0.0, self.flicker_offset, None);
+        self.animation = animation;
+        self
+    }
+
+    /// Change how the light falls off, like switching a lantern between glowing and beaming.
+    pub fn set_kind(&mut self, kind: LightFalloffKind) {
+        self.kind = kind;
+    }
+
+    /// Point a cone light at the given angle. Circular lights don't care.
+    pub fn set_facing(&mut self, angle: f32) {
+        if let LightFalloffKind::Cone { facing, .. } = &mut self.kind {
+            *facing = angle;
+        }
+    }
+
+    /// How far away this light stops lighting anything.
+    pub fn radius(&self) -> f32 {
+        let m = match self.kind {
+            LightFalloffKind::Circular { m } => m,
+            LightFalloffKind::Cone { m, .. } => m,
+        };
+        if m > 0.0 {
+            (1.0 / m).min(MAX_LIGHT_RADIUS)
+        } else {
+            MAX_LIGHT_RADIUS
+        }
+    }
+
+    /// Get the shader that draws this light into the light buffer,
+    /// with its uniforms set for a light at `pos`.
     ///
-    /// I ripped this directly from Upper Crust and have forgotten how it works
-    pub fn get_color(&self, pos: Vec2, target: Vec2) -> Vec3 {
-        match self.kind {
+    /// The falloff maths is ripped directly from Upper Crust; it lives in the shaders now.
+    fn material(&self, pos: Vec2, assets: &Assets) -> Material {
+        let material = match self.kind {
             LightFalloffKind::Circular { m } => {
-                let dist = target.distance(pos);
-                let l = -m * dist + 1.0;
-                self.color * l
+                let material = assets.shaders.light_circular;
+                material.set_uniform("Falloff", m);
+                material
             }
             LightFalloffKind::Cone { m, spread, facing } => {
-                // Untransform the position by the facing dir
-                let rotmat = Mat2::from_scale_angle(vec2(1.0, 1.0), -facing);
-                let delta = rotmat * (target - pos);
-
-                let l = if delta.x >= 0.0 {
-                    (1.0 - m * delta.x - delta.y.abs() / delta.x / spread).max(0.0)
-                } else {
-                    0.0
-                } + (1.0 - m * delta.length_squared() * 5.0).max(0.0);
-                self.color * l.clamp(0.0, 1.0)
+                let material = assets.shaders.light_cone;
+                material.set_uniform("Falloff", m);
+                material.set_uniform("Spread", spread);
+                material.set_uniform("Facing", facing);
+                material
             }
+        };
+        material.set_uniform("LightPos", pos);
+        material.set_uniform("LightColor", self.color * self.brightness);
+        material
+    }
+}
+
+/// Step every light's animation forward.
+///
+/// Lights on things with a `LimitedTimeOffer` can fade out as it runs down,
+/// so run this after the timers tick.
+pub fn system_animate_lights(world: &mut World, physics: &PhysicsWorld) {
+    let dt = physics.integration_params.dt;
+    for (_, (light, timer)) in world.query_mut::<(&mut Illuminator, Option<&LimitedTimeOffer>)>() {
+        light.age += dt;
+        light.brightness = light.animation.brightness(
+            light.age,
+            light.flicker_offset,
+            timer.map(|timer| timer.time_left()),
+        );
+    }
+}
+
+/// Smooth noise from 0 to 1 that drifts to a new random value at every whole number.
+fn value_noise(x: f32) -> f32 {
+    // Cheap integer hash, good enough to not see any pattern in
+    let hash = |n: i32| {
+        let mut n = (n as u32).wrapping_mul(0x27d4eb2d);
+        n ^= n >> 15;
+        n = n.wrapping_mul(0x2c1b3c6d);
+        n ^= n >> 12;
+        (n & 0xffff) as f32 / 0xffff as f32
+    };
+    let cell = x.floor();
+    let t = x - cell;
+    let t = t * t * (3.0 - 2.0 * t);
+    let cell = cell as i32;
+    hash(cell) * (1.0 - t) + hash(cell.wrapping_add(1)) * t
+}
+
+/// Draw every `Illuminator` into the `Lights` layer looking at `view`, with walls casting shadows.
+///
+/// Each light is drawn as the polygon of what it can see, and its shader does the falloff.
+/// They're added together on top of the `ambient` light.
+///
+/// Lights shining through something with a `LightFilter` get drawn into the `LightScratch` layer first
+/// so the filter only tints that light.
+pub fn system_draw_lights(
+    world: &World,
+    physics: &PhysicsWorld,
+    assets: &Assets,
+    layers: &RenderLayers,
+    view: Rect,
+    ambient: Vec3,
+) {
+    let target_cam = layers.camera(RenderLayer::Lights, view);
+    let scratch_cam = layers.camera(RenderLayer::LightScratch, view);
+    let scratch = layers.target(RenderLayer::LightScratch, view);
+
+    set_camera(&target_cam);
+    clear_background(Color::from_vec(ambient.extend(1.0)));
+    for_each_light(world, physics, |light, polygon, filters| {
+        let origin = polygon.origin();
+        let points: Vec<_> = polygon.points().collect();
+        if points.is_empty() {
+            return;
+        }
+
+        if !filters.is_empty() {
+            set_camera(&scratch_cam);
+            clear_background(BLACK);
         }
+
+        gl_use_material(light.material(origin, assets));
+        draw_fan(origin, &points, WHITE);
+
+        if !filters.is_empty() {
+            gl_use_material(assets.shaders.light_filter);
+            for (rect, filter) in filters {
+                let shadow = shadow_of(origin, *rect, light.radius());
+                // It's convex, so the middle can see all of it
+                let middle =
+                    shadow.iter().fold(Vec2::ZERO, |acc, p| acc + *p) / shadow.len() as f32;
+                draw_fan(middle, &shadow, Color::from_vec(filter.tint.extend(1.0)));
+            }
+
+            set_camera(&target_cam);
+            gl_use_material(assets.shaders.light_add);
+            draw_texture_ex(
+                scratch.texture,
+                view.x,
+                view.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(view.size()),
+                    ..Default::default()
+                },
+            );
+        }
+    });
+    gl_use_default_material();
+}
+
+/// Draw the outlines of what each light can see.
+pub fn system_draw_light_polygons(world: &World, physics: &PhysicsWorld) {
+    for_each_light(world, physics, |light, polygon, _| {
+        let color = Color::from_vec(light.color.extend(1.0));
+        let points: Vec<_> = polygon.points().collect();
+        for (idx, &start) in points.iter().enumerate() {
+            let end = points[(idx + 1) % points.len()];
+            // 1/16 = 1 pixel
+            draw_line(start.x, start.y, end.x, end.y, 1.0 / 16.0, color);
+        }
+    });
+}
+
+/// Draw triangles from `center` to each pair of neighboring points, looping back around.
+fn draw_fan(center: Vec2, points: &[Vec2], color: Color) {
+    for (idx, &start) in points.iter().enumerate() {
+        let end = points[(idx + 1) % points.len()];
+        draw_triangle(center, start, end, color);
+    }
+}
+
+/// Work out what each light in the world can see, and call `f` with it
+/// and the see-through things (as their bounding boxes) in its reach.
+fn for_each_light(
+    world: &World,
+    physics: &PhysicsWorld,
+    mut f: impl FnMut(&Illuminator, &VisibilityPolygon, &[(Rect, &LightFilter)]),
+) {
+    let lighting_groups = InteractionGroups::new(
+        collider_groups::GROUP_LIGHTING,
+        collider_groups::FILTER_LIGHTING,
+    );
+
+    let mut filter_query = world.query::<(&HasCollider, &LightFilter)>();
+    let all_filters: Vec<_> = filter_query
+        .iter()
+        .map(|(_, (coll_h, filter))| {
+            let aabb = physics.colliders.get(**coll_h).unwrap().compute_aabb();
+            let size = aabb.maxs - aabb.mins;
+            (Rect::new(aabb.mins.x, aabb.mins.y, size.x, size.y), filter)
+        })
+        .collect();
+
+    for (_, (coll_h, light)) in world.query::<(&HasCollider, &Illuminator)>().iter() {
+        let coll = physics.colliders.get(**coll_h).unwrap();
+        let origin: Vec2 = coll.compute_aabb().center().into();
+        let radius = light.radius();
+
+        // Only the walls in reach can cast shadows
+        let reach = AABB::new(
+            Point2::new(origin.x - radius, origin.y - radius),
+            Point2::new(origin.x + radius, origin.y + radius),
+        );
+        let mut occluders = Vec::new();
+        physics
+            .query_pipeline
+            .colliders_with_aabb_intersecting_aabb(&reach, |handle| {
+                if let Some(other) = physics.colliders.get(*handle) {
+                    // See-through things are handled separately
+                    let entity = Entity::from_bits(other.user_data as u64);
+                    if other.collision_groups().test(lighting_groups)
+                        && world.get::<LightFilter>(entity).is_err()
+                    {
+                        let aabb = other.compute_aabb();
+                        let size = aabb.maxs - aabb.mins;
+                        occluders.push(Rect::new(aabb.mins.x, aabb.mins.y, size.x, size.y));
+                    }
+                }
+                // keep looking
+                true
+            });
+
+        let reach_rect = Rect::new(
+            origin.x - radius,
+            origin.y - radius,
+            radius * 2.0,
+            radius * 2.0,
+        );
+        let filters: Vec<_> = all_filters
+            .iter()
+            .filter(|(rect, _)| rect.overlaps(&reach_rect))
+            .map(|(rect, filter)| (*rect, *filter))
+            .collect();
+
+        f(
+            light,
+            &VisibilityPolygon::new(origin, radius, &occluders),
+            &filters,
+        );
     }
 }
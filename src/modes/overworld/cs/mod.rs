@@ -6,6 +6,7 @@ pub mod damage;
 pub mod dazing;
 pub mod debug;
 pub mod explosions;
+pub mod facing;
 pub mod light;
 pub mod limited_time_offer;
 pub mod particles;
@@ -14,3 +15,5 @@ pub mod player;
 pub mod projectiles;
 pub mod shrine;
 pub mod spells;
+pub mod sprite;
+pub mod tilemap;
@@ -5,6 +5,7 @@ use crate::modes::overworld::{
     cs::{
         explosions::{handler_explosion, Explosion},
         player::Player,
+        shrine::Shrine,
     },
     physics::PhysicsWorld,
     WorldExt,
@@ -96,6 +97,16 @@ pub fn system_run_physics(world: &mut World, physics: &mut PhysicsWorld) {
         let c2 = physics.colliders.get(ev.collider2).unwrap();
         let e2 = Entity::from_bits(c2.user_data as _);
 
+        // Walk into a shrine? check both permutations
+        if ev.intersecting {
+            for (e1, e2) in [(e1, e2), (e2, e1)] {
+                let level = world.get::<Shrine>(e1).ok().map(|shrine| shrine.level());
+                if let (Some(level), Ok(mut player)) = (level, world.get_mut::<Player>(e2)) {
+                    player.entered_shrine = Some((e1, level));
+                }
+            }
+        }
+
         // Explode? check both permutations
         for (e1, e2) in [(e1, e2), (e2, e1)] {
             let e1_explodes = world.get::<Explosion>(e1).is_ok();
@@ -1,8 +1,8 @@
 use std::f32::consts::TAU as TAU32;
 
 use cogs_gamedev::controls::InputHandler;
-use hecs::World;
-use macroquad::prelude::{error, info, vec2, SKYBLUE};
+use hecs::{Entity, World};
+use macroquad::prelude::{error, info, Vec2, SKYBLUE};
 use quad_rand::compat::QuadRand;
 use rand::Rng;
 use rapier2d::{na::Vector2, prelude::*};
@@ -10,7 +10,13 @@ use rapier2d::{na::Vector2, prelude::*};
 use crate::{
     controls::{Control, InputSubscriber},
     modes::overworld::{
-        cs::{explosions::Explosion, physics::HasRigidBody},
+        cs::{
+            explosions::Explosion,
+            facing::Facing,
+            light::{Illuminator, LightFalloffKind},
+            physics::{HasCollider, HasRigidBody},
+            sprite::{Animation, SpriteDirection},
+        },
         physics::{collider_groups, PhysicsWorld},
         spells::{
             casting::{CastResult, PatternDrawState, SpellCaster},
@@ -18,6 +24,7 @@ use crate::{
         },
         WorldExt,
     },
+    utils::coords::ViewTransform,
     HEIGHT, WIDTH,
 };
 
@@ -32,6 +39,17 @@ mod consts {
     pub const WALK_IMPULSE: f32 = 1.0;
     /// Default damping for the player
     pub const DAMPING: f32 = 20.0;
+
+    /// How harshly the player's light falls off when it's glowing all around.
+    pub const GLOW_FALLOFF: f32 = 0.1;
+    /// How harshly the player's light falls off when it's a lantern beam.
+    /// It gets to reach further since it's all going one way.
+    pub const LANTERN_FALLOFF: f32 = 0.06;
+    /// How wide the lantern beam is.
+    pub const LANTERN_SPREAD: f32 = 0.6;
+
+    /// Below this speed the player stands still instead of walking.
+    pub const WALK_ANIM_SPEED: f32 = 0.5;
 }
 
 /// Component for things that are the player.
@@ -41,11 +59,20 @@ mod consts {
 pub struct Player {
     /// This is Some if we are currently drawing a spell.
     pub wip_spell: Option<SpellCaster>,
+    /// This is Some if we walked into a shrine this frame,
+    /// and contains the shrine and the level it leads to.
+    pub entered_shrine: Option<(Entity, u64)>,
+    /// Whether our light is a lantern beam pointing at the mouse.
+    pub lantern: bool,
 }
 
 impl Player {
     pub fn new() -> Self {
-        Self { wip_spell: None }
+        Self {
+            wip_spell: None,
+            entered_shrine: None,
+            lantern: false,
+        }
     }
 }
 
@@ -123,6 +150,70 @@ pub fn system_player_inputs(
     }
 }
 
+/// Switch the player's lantern on and off, and point it at the mouse.
+///
+/// `transform` is the camera's, to find where the mouse is in the world.
+pub fn system_player_lantern(
+    world: &mut World,
+    physics: &PhysicsWorld,
+    controls: &InputSubscriber,
+    transform: &ViewTransform,
+) {
+    if let Some(player_h) = world.get_player() {
+        let (player, coll_h, light, facing) = world
+            .query_one_mut::<(&mut Player, &HasCollider, &mut Illuminator, &mut Facing)>(player_h)
+            .unwrap();
+
+        if controls.clicked_down(Control::Lantern) {
+            player.lantern = !player.lantern;
+            light.set_kind(if player.lantern {
+                LightFalloffKind::Cone {
+                    m: consts::LANTERN_FALLOFF,
+                    spread: consts::LANTERN_SPREAD,
+                    facing: 0.0,
+                }
+            } else {
+                LightFalloffKind::Circular {
+                    m: consts::GLOW_FALLOFF,
+                }
+            });
+        }
+
+        let coll = physics.colliders.get(coll_h.0).unwrap();
+        let pos: Vec2 = coll.compute_aabb().center().into();
+        let aim = controls.mouse_world_pos(transform) - pos;
+        if aim.length_squared() > 0.0 {
+            facing.set_angle(aim.y.atan2(aim.x));
+        }
+    }
+}
+
+/// Pick the player's animation from what they're up to.
+pub fn system_player_animation(world: &mut World, physics: &PhysicsWorld) {
+    if let Some(player_h) = world.get_player() {
+        let (player, rb_h, anim) = world
+            .query_one_mut::<(&Player, &HasRigidBody, &mut Animation)>(player_h)
+            .unwrap();
+        let rb = physics.rigid_bodies.get(rb_h.0).unwrap();
+        let vel: Vec2 = (*rb.linvel()).into();
+
+        let walking = vel.length() > consts::WALK_ANIM_SPEED;
+        let direction = if walking {
+            SpriteDirection::from_vec(vel)
+        } else {
+            anim.direction()
+        };
+        let clip = if player.wip_spell.is_some() {
+            "cast"
+        } else if walking {
+            "walk"
+        } else {
+            "idle"
+        };
+        anim.play(clip, direction);
+    }
+}
+
 pub fn system_draw_spellcaster(world: &World, controls: &InputSubscriber) {
     use macroquad::prelude::*;
 
@@ -8,4 +8,9 @@ impl Shrine {
     pub fn new(level: u64) -> Self {
         Self { level }
     }
+
+    /// Get the level this shrine leads to.
+    pub fn level(&self) -> u64 {
+        self.level
+    }
 }
@@ -0,0 +1,188 @@
+use hecs::World;
+use macroquad::prelude::{draw_texture_ex, vec2, DrawTextureParams, Vec2, WHITE};
+
+use crate::{
+    assets::{Assets, Clip, SpriteSheet, SpriteSheetId},
+    modes::overworld::physics::PhysicsWorld,
+};
+
+use super::physics::HasCollider;
+
+/// How many sprite pixels there are to one physics unit.
+const SPRITE_RESOLUTION: f32 = 16.0;
+
+/// Component for things drawn with a frame off a sprite sheet.
+///
+/// The sprite stands on the bottom of its collider, centered across it.
+#[derive(Debug)]
+pub struct Sprite {
+    sheet: SpriteSheetId,
+    /// Index of the frame on the sheet to draw.
+    frame: usize,
+    /// Draw it mirrored left-to-right.
+    flip_x: bool,
+}
+
+impl Sprite {
+    pub fn new(sheet: SpriteSheetId) -> Self {
+        Self {
+            sheet,
+            frame: 0,
+            flip_x: false,
+        }
+    }
+}
+
+/// Which way something animated is facing, for picking variants of clips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl SpriteDirection {
+    /// Which way the vector is mostly pointing.
+    pub fn from_vec(v: Vec2) -> Self {
+        if v.x.abs() >= v.y.abs() {
+            if v.x < 0.0 {
+                SpriteDirection::Left
+            } else {
+                SpriteDirection::Right
+            }
+        } else if v.y < 0.0 {
+            SpriteDirection::Up
+        } else {
+            SpriteDirection::Down
+        }
+    }
+
+    /// What goes on the end of a clip's name for this direction's variant.
+    fn suffix(self) -> &'static str {
+        match self {
+            SpriteDirection::Left => "left",
+            SpriteDirection::Right => "right",
+            SpriteDirection::Up => "up",
+            SpriteDirection::Down => "down",
+        }
+    }
+
+    /// The direction whose variant can be flipped to get this one.
+    fn mirror(self) -> Option<Self> {
+        match self {
+            SpriteDirection::Left => Some(SpriteDirection::Right),
+            SpriteDirection::Right => Some(SpriteDirection::Left),
+            SpriteDirection::Up | SpriteDirection::Down => None,
+        }
+    }
+}
+
+/// Component for playing clips off a `Sprite`'s sheet.
+#[derive(Debug)]
+pub struct Animation {
+    /// Name of the clip, without any direction on the end.
+    clip: &'static str,
+    direction: SpriteDirection,
+    /// Which frame of the clip we're on.
+    frame_idx: usize,
+    /// How long we've been on that frame, in seconds.
+    frame_time: f32,
+}
+
+impl Animation {
+    pub fn new(clip: &'static str, direction: SpriteDirection) -> Self {
+        Self {
+            clip,
+            direction,
+            frame_idx: 0,
+            frame_time: 0.0,
+        }
+    }
+
+    /// Play the given clip, facing the given way.
+    ///
+    /// If that clip is already playing it keeps going from where it was, even if it turns around.
+    pub fn play(&mut self, clip: &'static str, direction: SpriteDirection) {
+        if clip != self.clip {
+            self.clip = clip;
+            self.frame_idx = 0;
+            self.frame_time = 0.0;
+        }
+        self.direction = direction;
+    }
+
+    pub fn direction(&self) -> SpriteDirection {
+        self.direction
+    }
+
+    /// Find the clip to play on the sheet, and whether it needs to be flipped.
+    ///
+    /// This tries the variant for our direction first, then a mirrored one,
+    /// then the clip with no direction at all.
+    fn find_clip<'a>(&self, sheet: &'a SpriteSheet) -> Option<(&'a Clip, bool)> {
+        let variant = |dir: SpriteDirection| sheet.clip(&format!("{}_{}", self.clip, dir.suffix()));
+        if let Some(clip) = variant(self.direction) {
+            return Some((clip, false));
+        }
+        if let Some(clip) = self.direction.mirror().and_then(variant) {
+            return Some((clip, true));
+        }
+        sheet.clip(self.clip).map(|clip| (clip, false))
+    }
+}
+
+/// Step animations forward and point their sprites at the right frame.
+pub fn system_animate_sprites(world: &mut World, physics: &PhysicsWorld, assets: &Assets) {
+    let dt = physics.integration_params.dt;
+    for (_, (sprite, anim)) in world.query_mut::<(&mut Sprite, &mut Animation)>() {
+        let sheet = assets.textures.sprites.get(sprite.sheet);
+        let (clip, flip_x) = match anim.find_clip(sheet) {
+            Some(it) => it,
+            None => continue,
+        };
+        let frames = clip.frames();
+        if frames.is_empty() {
+            continue;
+        }
+
+        // Turning around can land on a clip with a different length
+        anim.frame_idx %= frames.len();
+        anim.frame_time += dt;
+        loop {
+            let duration = sheet.frame(frames[anim.frame_idx]).duration;
+            if duration <= 0.0 || anim.frame_time < duration {
+                break;
+            }
+            anim.frame_time -= duration;
+            anim.frame_idx = (anim.frame_idx + 1) % frames.len();
+        }
+
+        sprite.frame = frames[anim.frame_idx];
+        sprite.flip_x = flip_x;
+    }
+}
+
+pub fn system_draw_sprites(world: &World, physics: &PhysicsWorld, assets: &Assets) {
+    for (_, (sprite, coll_h)) in world.query::<(&Sprite, &HasCollider)>().iter() {
+        let coll = physics.colliders.get(coll_h.0).unwrap();
+        let aabb = coll.compute_aabb();
+        let sheet = assets.textures.sprites.get(sprite.sheet);
+        let region = sheet.frame(sprite.frame).region;
+
+        let size = region.size() / SPRITE_RESOLUTION;
+        let corner = vec2(aabb.center().x - size.x / 2.0, aabb.maxs.y - size.y);
+        draw_texture_ex(
+            sheet.texture,
+            corner.x,
+            corner.y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(size),
+                source: Some(region),
+                flip_x: sprite.flip_x,
+                ..Default::default()
+            },
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use ahash::AHashMap;
+use cogs_gamedev::grids::{Direction4, Direction8, ICoord};
+use hecs::World;
+use macroquad::prelude::{
+    draw_texture_ex, vec2, Color, DrawTextureParams, Rect, Texture2D, Vec2, WHITE,
+};
+
+use crate::{
+    assets::{Assets, TileAtlases},
+    modes::overworld::procgen::{
+        tile_to_chunk, tile_to_world, world_to_tile, ChunkMap, Palette, Tile, TileAtlas, TILE_SCALE,
+    },
+};
+
+/// Size of one tile in the atlases, in pixels.
+const ATLAS_TILE_SIZE: f32 = 16.0;
+/// How many variants of each material there are across an atlas row.
+const ATLAS_VARIANTS: isize = 4;
+/// How wide paths are in the middle of their tile, in world units.
+const PATH_WIDTH: f32 = TILE_SCALE / 2.0;
+
+/// Component holding the tiles of the loaded chunks of the level, for drawing.
+///
+/// The tiles don't get an entity each; solid ones are merged into bigger colliders
+/// when their chunk is put in the world, and this draws all of them in one go.
+pub struct Tilemap {
+    palette: Palette,
+    chunks: AHashMap<ICoord, ChunkMap>,
+}
+
+impl Tilemap {
+    pub fn new(palette: Palette) -> Self {
+        Self {
+            palette,
+            chunks: AHashMap::new(),
+        }
+    }
+
+    /// Start drawing the given chunk.
+    pub fn insert_chunk(&mut self, chunk: ChunkMap) {
+        self.chunks.insert(chunk.coord(), chunk);
+    }
+
+    /// Stop drawing the chunk at the given chunk position.
+    pub fn remove_chunk(&mut self, coord: ICoord) {
+        self.chunks.remove(&coord);
+    }
+
+    /// Get the tile at the given position, or None if its chunk isn't loaded.
+    fn get(&self, pos: ICoord) -> Option<Tile> {
+        self.chunks.get(&tile_to_chunk(pos))?.get(pos)
+    }
+
+    /// Bitmask of which neighbors are "the same" as this tile, according to the predicate.
+    /// Bit `n` is set if the neighbor in the `n`th direction of `Direction8::DIRECTIONS` is.
+    ///
+    /// Neighbors that aren't loaded count as the same, so the edge of the map doesn't get outlined.
+    fn neighbor_mask(&self, pos: ICoord, same: impl Fn(Tile) -> bool) -> u8 {
+        Direction8::DIRECTIONS
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (idx, dir)| match self.get(pos + *dir) {
+                Some(tile) if !same(tile) => mask,
+                _ => mask | (1 << idx),
+            })
+    }
+}
+
+/// Draw all the tiles inside `view`, which is in world coordinates.
+pub fn system_draw_tilemap(world: &World, assets: &Assets, view: Rect) {
+    let min = world_to_tile(view.point());
+    let max = world_to_tile(view.point() + view.size());
+    let atlases = &assets.textures.tiles;
+    for (_, tilemap) in world.query::<&Tilemap>().iter() {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let pos = ICoord::new(x, y);
+                if let Some(tile) = tilemap.get(pos) {
+                    draw_tile(tilemap, atlases, pos, tile);
+                }
+            }
+        }
+    }
+}
+
+fn draw_tile(tilemap: &Tilemap, atlases: &TileAtlases, pos: ICoord, tile: Tile) {
+    let corner = tile_to_world(pos) - vec2(TILE_SCALE, TILE_SCALE) / 2.0;
+    let variant = variant_at(pos);
+    let palette = &tilemap.palette;
+    let atlas_row = |tile: Tile| {
+        let look = palette.look(tile);
+        let tex = match look.atlas {
+            TileAtlas::Ground => atlases.ground,
+            TileAtlas::GrassBuildings => atlases.grass_buildings,
+            TileAtlas::TerracottaGrids => atlases.terracotta_grids,
+        };
+        (tex, look.row)
+    };
+
+    match tile {
+        Tile::Ground | Tile::Floor => {
+            let (tex, row) = atlas_row(tile);
+            draw_atlas(tex, row, variant, corner);
+        }
+        Tile::Rock => {
+            let (tex, row) = atlas_row(tile);
+            draw_atlas(tex, row, variant, corner);
+
+            // Shade the sides that face open ground
+            let mask = tilemap.neighbor_mask(pos, |t| t.is_solid());
+            let color = darken(palette.look(tile).color, 0.6);
+            draw_edges(atlases.edges, corner, mask, color, false);
+        }
+        Tile::Wall => {
+            let (tex, row) = atlas_row(tile);
+            draw_atlas(tex, row, variant, corner);
+
+            // Outline where the wall stops, inner corners too
+            let mask = tilemap.neighbor_mask(pos, |t| matches!(t, Tile::Wall));
+            let color = darken(palette.look(tile).color, 0.5);
+            draw_edges(atlases.edges, corner, mask, color, true);
+        }
+        Tile::Path => {
+            // Paths go over the ground, and join up with any paths or doors next to them
+            let (tex, row) = atlas_row(Tile::Ground);
+            draw_atlas(tex, row, variant, corner);
+
+            let (tex, row) = atlas_row(tile);
+            let mask = tilemap.neighbor_mask(pos, |t| matches!(t, Tile::Path | Tile::Floor));
+            let inset = (TILE_SCALE - PATH_WIDTH) / 2.0;
+            draw_atlas_part(
+                tex,
+                row,
+                variant,
+                corner,
+                Rect::new(inset, inset, PATH_WIDTH, PATH_WIDTH),
+                WHITE,
+            );
+
+            for &dir in Direction4::DIRECTIONS.iter() {
+                if mask & (1 << dir8_index(dir4_to_8(dir))) == 0 {
+                    continue;
+                }
+                let arm = match dir {
+                    Direction4::North => Rect::new(inset, 0.0, PATH_WIDTH, inset),
+                    Direction4::South => Rect::new(inset, TILE_SCALE - inset, PATH_WIDTH, inset),
+                    Direction4::West => Rect::new(0.0, inset, inset, PATH_WIDTH),
+                    Direction4::East => Rect::new(TILE_SCALE - inset, inset, inset, PATH_WIDTH),
+                };
+                draw_atlas_part(tex, row, variant, corner, arm, WHITE);
+            }
+        }
+    }
+}
+
+/// Pick a variant for the tile in a way that looks random but stays put.
+fn variant_at(pos: ICoord) -> isize {
+    let hash = (pos.x.wrapping_mul(73856093) ^ pos.y.wrapping_mul(19349663)) >> 4;
+    hash.rem_euclid(ATLAS_VARIANTS)
+}
+
+/// Draw an atlas tile over the whole tile with its top-left at `corner`.
+fn draw_atlas(tex: Texture2D, row: u8, variant: isize, corner: Vec2) {
+    draw_atlas_part(
+        tex,
+        row,
+        variant,
+        corner,
+        Rect::new(0.0, 0.0, TILE_SCALE, TILE_SCALE),
+        WHITE,
+    );
+}
+
+/// Draw part of an atlas tile, tinted with `color`.
+/// `part` is in world units relative to the tile's top-left corner.
+fn draw_atlas_part(
+    tex: Texture2D,
+    row: u8,
+    variant: isize,
+    corner: Vec2,
+    part: Rect,
+    color: Color,
+) {
+    let px_per_unit = ATLAS_TILE_SIZE / TILE_SCALE;
+    draw_texture_ex(
+        tex,
+        corner.x + part.x,
+        corner.y + part.y,
+        color,
+        DrawTextureParams {
+            dest_size: Some(vec2(part.w, part.h)),
+            source: Some(Rect::new(
+                variant as f32 * ATLAS_TILE_SIZE + part.x * px_per_unit,
+                row as f32 * ATLAS_TILE_SIZE + part.y * px_per_unit,
+                part.w * px_per_unit,
+                part.h * px_per_unit,
+            )),
+            ..Default::default()
+        },
+    );
+}
+
+/// Piece of the edge atlas, in the order they go across it.
+///
+/// Each quarter of a tile is drawn from the same quarter of one of these,
+/// picked by whether the two sides and the diagonal next to that quarter are connected.
+#[derive(Debug, Clone, Copy)]
+enum EdgePiece {
+    /// Neither side is connected.
+    OuterCorner,
+    /// Only the side across is connected, so the edge runs across the top or bottom.
+    Horizontal,
+    /// Only the side up or down is connected, so the edge runs down the left or right.
+    Vertical,
+    /// Both sides are connected but the diagonal isn't.
+    InnerCorner,
+}
+
+impl EdgePiece {
+    /// Pick the piece for a quarter of a tile, or None if it doesn't need an edge.
+    fn for_quarter(
+        vertical: bool,
+        horizontal: bool,
+        diagonal: bool,
+        inner_corners: bool,
+    ) -> Option<Self> {
+        match (vertical, horizontal) {
+            (false, false) => Some(EdgePiece::OuterCorner),
+            (false, true) => Some(EdgePiece::Horizontal),
+            (true, false) => Some(EdgePiece::Vertical),
+            (true, true) if inner_corners && !diagonal => Some(EdgePiece::InnerCorner),
+            (true, true) => None,
+        }
+    }
+}
+
+/// Draw edges from the edge atlas along the sides of the tile that aren't connected according to the mask.
+///
+/// If `inner_corners` is set, also put little corners in where both sides
+/// are connected but the diagonal isn't.
+fn draw_edges(edges: Texture2D, corner: Vec2, mask: u8, color: Color, inner_corners: bool) {
+    let connected = |dir: Direction8| mask & (1 << dir8_index(dir)) != 0;
+    let half = TILE_SCALE / 2.0;
+
+    let quarters = [
+        (
+            Direction8::North,
+            Direction8::West,
+            Direction8::NorthWest,
+            0.0,
+            0.0,
+        ),
+        (
+            Direction8::North,
+            Direction8::East,
+            Direction8::NorthEast,
+            half,
+            0.0,
+        ),
+        (
+            Direction8::South,
+            Direction8::East,
+            Direction8::SouthEast,
+            half,
+            half,
+        ),
+        (
+            Direction8::South,
+            Direction8::West,
+            Direction8::SouthWest,
+            0.0,
+            half,
+        ),
+    ];
+    for &(vertical, horizontal, diagonal, dx, dy) in quarters.iter() {
+        let piece = EdgePiece::for_quarter(
+            connected(vertical),
+            connected(horizontal),
+            connected(diagonal),
+            inner_corners,
+        );
+        if let Some(piece) = piece {
+            draw_atlas_part(
+                edges,
+                0,
+                piece as isize,
+                corner,
+                Rect::new(dx, dy, half, half),
+                color,
+            );
+        }
+    }
+}
+
+fn dir8_index(dir: Direction8) -> usize {
+    Direction8::DIRECTIONS
+        .iter()
+        .position(|d| *d == dir)
+        .unwrap()
+}
+
+fn dir4_to_8(dir: Direction4) -> Direction8 {
+    match dir {
+        Direction4::North => Direction8::North,
+        Direction4::East => Direction8::East,
+        Direction4::South => Direction8::South,
+        Direction4::West => Direction8::West,
+    }
+}
+
+fn darken(color: Color, by: f32) -> Color {
+    Color::new(color.r * by, color.g * by, color.b * by, color.a)
+}
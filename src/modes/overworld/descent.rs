@@ -0,0 +1,133 @@
+use hecs::World;
+use macroquad::prelude::{draw_texture, RenderTarget, WHITE};
+
+use crate::modes::{canvas_target, draw_into_target, TransitionStyle};
+
+use super::{
+    physics::PhysicsWorld,
+    procgen::{clear_level, LevelStreamer, COLLIDERS_PER_FRAME},
+};
+
+/// How long we look at the shrine before going through it, in seconds.
+const LEAVE_TIME: f32 = 0.6;
+/// How long the old level takes to blend into the new one, in seconds.
+const BLEND_TIME: f32 = 0.6;
+/// How the old level blends into the new one.
+/// This is the same as going from loading into the overworld.
+const BLEND_STYLE: TransitionStyle = TransitionStyle::Crossfade;
+
+/// State for going down through a shrine to the next level.
+///
+/// The last frame of the old level is kept on screen while the chunks around the start of
+/// the next level are generated and swapped in around the player, and then it's blended into
+/// the new level the same way `ModeTransition` blends gamemodes.
+pub struct Descent {
+    stage: DescentStage,
+    /// Seconds we've been in the current stage
+    elapsed: f32,
+
+    /// The old level, as it looked on the last frame before it went away.
+    from_target: RenderTarget,
+    /// The new level, to blend in over the old one.
+    to_target: RenderTarget,
+}
+
+enum DescentStage {
+    /// The next level, which is generating while we look at the shrine.
+    Leaving(Box<LevelStreamer>),
+    /// The next level is in place, and its chunks are going into the world.
+    Loading,
+    Blending,
+}
+
+impl Descent {
+    /// Start going down to the given depth.
+    pub fn new(seed: u64, depth: u64) -> Self {
+        Self {
+            stage: DescentStage::Leaving(Box::new(LevelStreamer::new(seed, depth))),
+            elapsed: 0.0,
+            from_target: canvas_target(),
+            to_target: canvas_target(),
+        }
+    }
+
+    /// Keep going down. Returns `true` once we've arrived and blended into the new level.
+    ///
+    /// Once the old level is hidden the new level gets swapped into `level`.
+    pub fn update(
+        &mut self,
+        dt: f32,
+        world: &mut World,
+        physics: &mut PhysicsWorld,
+        level: &mut LevelStreamer,
+    ) -> bool {
+        self.elapsed += dt;
+
+        let next = match &mut self.stage {
+            DescentStage::Leaving(next_level) => {
+                // may as well start generating while we look
+                next_level.prefetch();
+                if self.elapsed >= LEAVE_TIME {
+                    match std::mem::replace(&mut self.stage, DescentStage::Loading) {
+                        DescentStage::Leaving(next_level) => *level = *next_level,
+                        _ => unreachable!(),
+                    }
+                    clear_level(world, physics);
+                    Some(DescentStage::Loading)
+                } else {
+                    None
+                }
+            }
+            DescentStage::Loading => {
+                let start = level.start();
+                level.update(&[start], COLLIDERS_PER_FRAME, world, physics);
+                if level.is_ready() {
+                    level.place_player(world, physics);
+                    Some(DescentStage::Blending)
+                } else {
+                    None
+                }
+            }
+            DescentStage::Blending => return self.elapsed >= BLEND_TIME,
+        };
+
+        if let Some(next) = next {
+            self.stage = next;
+            self.elapsed = 0.0;
+        }
+        false
+    }
+
+    /// Whether the old level is being shown instead of the world right now,
+    /// so things can be moved around without anyone seeing.
+    pub fn is_hidden(&self) -> bool {
+        matches!(self.stage, DescentStage::Loading)
+    }
+
+    /// Draw the overworld with `draw_frame`, blending from the old level to the new one.
+    pub fn draw(&self, draw_frame: impl FnOnce()) {
+        match self.stage {
+            DescentStage::Leaving(_) => {
+                // Keep hold of this frame in case it's the last one of the old level
+                draw_into_target(self.from_target, draw_frame);
+                draw_texture(self.from_target.texture, 0.0, 0.0, WHITE);
+            }
+            DescentStage::Loading => draw_texture(self.from_target.texture, 0.0, 0.0, WHITE),
+            DescentStage::Blending => {
+                draw_into_target(self.to_target, draw_frame);
+                BLEND_STYLE.composite(
+                    self.from_target.texture,
+                    self.to_target.texture,
+                    self.elapsed / BLEND_TIME,
+                );
+            }
+        }
+    }
+}
+
+impl Drop for Descent {
+    fn drop(&mut self) {
+        self.from_target.delete();
+        self.to_target.delete();
+    }
+}
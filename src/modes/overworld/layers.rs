@@ -0,0 +1,154 @@
+//! Off-screen buffers the overworld gets drawn into before they're put together on the screen.
+//!
+//! Making render targets is expensive and they never get freed on their own,
+//! so we make each one once and only remake it when it needs to be a different size.
+
+use std::cell::Cell;
+
+use macroquad::prelude::*;
+
+use crate::{assets::Assets, HEIGHT, WIDTH};
+
+/// How many light pixels there are across/down to one physics unit
+const LIGHT_RESOLUTION: f32 = 8.0;
+
+/// Things that get drawn separately and put together at the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderLayer {
+    /// Tiles, entities and particles, in world units.
+    World,
+    /// The lights added up, in world units, at a lower resolution than the world.
+    Lights,
+    /// Where a single light gets drawn so it can be tinted by itself before it's added to the others.
+    LightScratch,
+    /// Things drawn over the top of everything that don't get lit, in screen pixels.
+    Ui,
+}
+
+impl RenderLayer {
+    /// How many of the layer's pixels there are across one unit of whatever it's looking at.
+    fn resolution(self) -> f32 {
+        match self {
+            RenderLayer::World => 16.0,
+            RenderLayer::Lights | RenderLayer::LightScratch => LIGHT_RESOLUTION,
+            RenderLayer::Ui => 1.0,
+        }
+    }
+
+    fn filter(self) -> FilterMode {
+        match self {
+            RenderLayer::World | RenderLayer::Ui => FilterMode::Nearest,
+            // The lights are lower-res than the world, so smooth them out
+            RenderLayer::Lights | RenderLayer::LightScratch => FilterMode::Linear,
+        }
+    }
+}
+
+/// One render target per `RenderLayer`, made the first time it's needed.
+///
+/// These are behind `Cell`s so they can be remade while drawing, which only gets `&self`.
+pub struct RenderLayers {
+    targets: [Cell<Option<RenderTarget>>; 4],
+}
+
+impl RenderLayers {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            targets: Default::default(),
+        }
+    }
+
+    /// Get the layer's render target, big enough to draw `view` at the layer's resolution.
+    ///
+    /// If it's the wrong size, the old one gets thrown out and a new one made.
+    pub fn target(&self, layer: RenderLayer, view: Rect) -> RenderTarget {
+        let size = view.size() * layer.resolution();
+        let (width, height) = (
+            (size.x.round() as u32).max(1),
+            (size.y.round() as u32).max(1),
+        );
+
+        let cell = &self.targets[layer as usize];
+        match cell.get() {
+            Some(target)
+                if target.texture.width() as u32 == width
+                    && target.texture.height() as u32 == height =>
+            {
+                target
+            }
+            old => {
+                if let Some(old) = old {
+                    old.delete();
+                }
+                let target = render_target(width, height);
+                target.texture.set_filter(layer.filter());
+                cell.set(Some(target));
+                target
+            }
+        }
+    }
+
+    /// Make a camera that draws `view` into the layer.
+    pub fn camera(&self, layer: RenderLayer, view: Rect) -> Camera2D {
+        Camera2D {
+            render_target: Some(self.target(layer, view)),
+            target: view.point() + view.size() / 2.0,
+            zoom: vec2(2.0, 2.0) / view.size(),
+            ..Default::default()
+        }
+    }
+
+    /// Start drawing `view` into the layer, clearing it out first.
+    ///
+    /// Remember to `push_camera_state` beforehand and pop it when you're done.
+    pub fn begin(&self, layer: RenderLayer, view: Rect) -> Camera2D {
+        let camera = self.camera(layer, view);
+        set_camera(&camera);
+        clear_background(BLANK);
+        camera
+    }
+
+    /// Put the layers together on the screen: the world lit by the lights, and the UI on top.
+    ///
+    /// The layers should already be drawn, looking at `view`.
+    pub fn composite(&self, assets: &Assets, view: Rect) {
+        let screen = Rect::new(0.0, 0.0, WIDTH, HEIGHT);
+        let draw_params = DrawTextureParams {
+            dest_size: Some(screen.size()),
+            ..Default::default()
+        };
+
+        assets
+            .shaders
+            .lighting
+            .set_texture("lights", self.target(RenderLayer::Lights, view).texture);
+        gl_use_material(assets.shaders.lighting);
+        draw_texture_ex(
+            self.target(RenderLayer::World, view).texture,
+            0.0,
+            0.0,
+            WHITE,
+            draw_params.clone(),
+        );
+        gl_use_default_material();
+
+        draw_texture_ex(
+            self.target(RenderLayer::Ui, screen).texture,
+            0.0,
+            0.0,
+            WHITE,
+            draw_params,
+        );
+    }
+}
+
+impl Drop for RenderLayers {
+    fn drop(&mut self) {
+        for cell in self.targets.iter() {
+            if let Some(target) = cell.take() {
+                target.delete();
+            }
+        }
+    }
+}
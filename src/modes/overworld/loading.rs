@@ -0,0 +1,115 @@
+use macroquad::prelude::{vec2, Color, WHITE};
+
+use crate::{
+    assets::Assets,
+    boilerplates::{FrameInfo, Gamemode, Transition},
+    controls::InputSubscriber,
+    modes::TransitionStyle,
+    utils::{
+        draw::hexcolor,
+        text::{Billboard, Markup, TextSpan},
+    },
+    HEIGHT, WIDTH,
+};
+
+use super::{procgen::COLLIDERS_PER_FRAME, ModeOverworld};
+
+/// Mode shown while the chunks around the start of the first level are generated
+/// and put into the world.
+///
+/// Once it's done it swaps itself out for the finished `ModeOverworld`.
+pub struct ModeLoading {
+    /// The overworld the level is going into.
+    /// This is only None once we've handed it off.
+    overworld: Option<ModeOverworld>,
+}
+
+impl ModeLoading {
+    /// Start loading the first level of the run with the given seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            overworld: Some(ModeOverworld::init(seed)),
+        }
+    }
+
+    /// Progress over the whole load, from 0 to 1.
+    fn progress(&self) -> f32 {
+        self.overworld
+            .as_ref()
+            .map_or(1.0, |overworld| overworld.level.progress())
+    }
+
+    fn description(&self) -> &'static str {
+        self.overworld
+            .as_ref()
+            .map_or("Done", |overworld| overworld.level.description())
+    }
+}
+
+impl Gamemode for ModeLoading {
+    fn update(
+        &mut self,
+        _controls: &InputSubscriber,
+        _frame_info: FrameInfo,
+        _assets: &Assets,
+    ) -> Transition {
+        let overworld = self.overworld.as_mut().unwrap();
+        let start = overworld.level.start();
+        overworld.level.update(
+            &[start],
+            COLLIDERS_PER_FRAME,
+            &mut overworld.world,
+            &mut overworld.physics,
+        );
+
+        if overworld.level.is_ready() {
+            let mut overworld = self.overworld.take().unwrap();
+            overworld
+                .level
+                .place_player(&mut overworld.world, &mut overworld.physics);
+            overworld.snap_camera_to_player();
+            return Transition::SwapAnimated(Box::new(overworld), TransitionStyle::Crossfade, 0.5);
+        }
+
+        Transition::None
+    }
+
+    fn draw(&self, assets: &Assets, _frame_info: FrameInfo, _controls: &InputSubscriber) {
+        use macroquad::prelude::*;
+
+        clear_background(hexcolor(0x21181bff));
+
+        let font = assets.textures.fonts.small;
+        let text = vec![TextSpan::new(
+            format!("{}...", self.description()),
+            Markup {
+                font,
+                color: WHITE,
+                kerning: 1.0,
+                vert_space: 1.0,
+                wave: None,
+            },
+        )];
+        Billboard::new(
+            text,
+            vec2(WIDTH / 2.0 - 64.0, HEIGHT / 2.0 - 32.0),
+            vec2(8.0, 19.0),
+            assets.textures.billboard_patch9,
+            8,
+            3,
+        )
+        .draw();
+
+        let bar_width = WIDTH / 2.0;
+        let bar_x = WIDTH / 2.0 - bar_width / 2.0;
+        let bar_y = HEIGHT / 2.0 + 24.0;
+        draw_rectangle(bar_x, bar_y, bar_width, 6.0, Color::new(0.2, 0.2, 0.2, 1.0));
+        draw_rectangle(
+            bar_x,
+            bar_y,
+            bar_width * self.progress(),
+            6.0,
+            hexcolor(0xffee83ff),
+        );
+    }
+}
@@ -1,58 +1,74 @@
+mod camera;
 mod cs;
 pub mod damage;
+mod descent;
+mod layers;
+mod loading;
 mod physics;
 mod procgen;
 mod spells;
+mod visibility;
 
-/// How much the player's velocity factors into the camera pos
-const PLAYER_VEL_CAMERA_INFLUENCE: f32 = 2.2;
-/// How much of the way to the target pos the camera pos tries to go
-/// (factoring in the dt)
-const CAMERA_SNAPPINESS: f32 = 0.8;
-/// How far away the camera's position must be away from the target before
-/// we move it
-const CAMERA_TOLERANCE: f32 = 1.5;
-/// If the camera is *too* far away, bring it back into this distance.
-const CAMERA_MAX_DIST: f32 = 16.0;
+pub use loading::ModeLoading;
+pub use procgen::run_map_tool;
 
-/// How many light pixels there are across/down to one physics unit
-const LIGHT_RESOLUTION: f32 = 1.0;
+/// How zoomed in the camera gets on a shrine you walk into.
+const DESCENT_ZOOM: f32 = 1.5;
+
+/// How much of the way to the level's ambient light the current ambient light goes each second,
+/// so it changes smoothly when you go down a level.
+const AMBIENT_SNAPPINESS: f32 = 1.5;
 
 use crate::{
-    assets::Assets,
+    assets::{Assets, SpriteSheetId},
     boilerplates::{FrameInfo, Gamemode, Transition},
     controls::{Control, InputSubscriber},
     modes::overworld::{
         cs::{
-            colored_box::{system_draw_colored_boxes, ColoredBox},
+            colored_box::system_draw_colored_boxes,
             dazing::{system_dazed, Dazeable},
             debug::system_draw_collision,
             explosions::system_cleanup_explosions,
-            light::{Illuminator, LightFalloffKind},
+            facing::{system_update_facing, Facing, FacingFrom},
+            light::{
+                system_animate_lights, system_draw_light_polygons, system_draw_lights, Illuminator,
+                LightFalloffKind,
+            },
             limited_time_offer::system_cleanup_limited_timers,
             particles::system_cleanup_particles,
             particles::system_draw_particles,
             physics::{system_run_physics, HasCollider, HasRigidBody},
-            player::{player_body_collider, system_draw_spellcaster, system_player_inputs, Player},
+            player::{
+                player_body_collider, system_draw_spellcaster, system_player_animation,
+                system_player_inputs, system_player_lantern, Player,
+            },
             projectiles::system_draw_projectiles,
             projectiles::system_update_and_cleanup_projectiles,
+            sprite::{
+                system_animate_sprites, system_draw_sprites, Animation, Sprite, SpriteDirection,
+            },
+            tilemap::system_draw_tilemap,
         },
-        physics::{collider_groups, PhysicsWorld},
+        physics::PhysicsWorld,
+        procgen::{LevelStreamer, COLLIDERS_PER_FRAME},
     },
+    modes::ModePause,
     HEIGHT, WIDTH,
 };
 
 use cogs_gamedev::controls::InputHandler;
 use hecs::{ComponentError, Entity, NoSuchEntity, World};
-use macroquad::prelude::{
-    info, vec3, Color, FilterMode, Image, Texture2D, Vec2, BLACK, BLANK, GRAY, ORANGE, WHITE,
-};
-use nalgebra::Point2;
+use macroquad::prelude::{info, vec3, Color, Vec3, BLANK, GRAY, WHITE};
 use quad_rand::compat::QuadRand;
 use rand::Rng;
 use rapier2d::prelude::*;
 
-use self::cs::damage::system_cleanup_dead;
+use self::{
+    camera::{system_camera_shake, Camera, CameraFocus},
+    cs::damage::system_cleanup_dead,
+    descent::Descent,
+    layers::{RenderLayer, RenderLayers},
+};
 
 /// Mode for the main playing state with the player running around dungeons.
 pub struct ModeOverworld {
@@ -61,17 +77,29 @@ pub struct ModeOverworld {
     /// Physics engine stuff
     physics: PhysicsWorld,
 
-    /// Place where the camera is
-    camera_pos: Vec2,
-    /// Place where the camera targets
-    camera_target: Vec2,
-
-    /// Cached light data
-    light_tex: Texture2D,
+    /// Seed the levels are generated from.
+    seed: u64,
+    /// How far down we are. The first level is depth 0.
+    depth: u64,
+    /// The level we're on, which loads in around the player as they move.
+    level: LevelStreamer,
+    /// This is Some while we're going through a shrine to the next level.
+    descent: Option<Descent>,
+
+    camera: Camera,
+
+    /// Light that's everywhere, as an RGB vector.
+    /// This eases towards the level's ambient light.
+    ambient: Vec3,
+    /// Off-screen buffers everything gets drawn into.
+    layers: RenderLayers,
 }
 
 impl ModeOverworld {
-    pub fn init() -> Self {
+    /// Make a new overworld with nothing but the player in it.
+    ///
+    /// Use a `ModeLoading` to put a map around them.
+    fn init(seed: u64) -> Self {
         let mut world = World::new();
         let mut physics = PhysicsWorld::new();
 
@@ -81,41 +109,77 @@ impl ModeOverworld {
             (
                 Player::new(),
                 Dazeable::new(),
-                ColoredBox(ORANGE),
+                Sprite::new(SpriteSheetId::Player),
+                Animation::new("idle", SpriteDirection::Down),
                 Illuminator::new(vec3(1.0, 1.0, 0.9), LightFalloffKind::Circular { m: 0.1 }),
+                Facing::new(0.0, FacingFrom::Fixed),
             ),
             coll,
             Some(rb),
         );
 
-        let seed: u64 = QuadRand.gen();
-        println!("seed: {}", seed);
-        procgen::generate_map(seed, 0, &mut world, &mut physics);
-
-        // new scope to appease borrowck
-        let center = {
-            let player = world.get_player().unwrap();
-            let coll_h = world.get::<HasCollider>(player).unwrap();
-            let coll = physics.colliders.get(**coll_h).unwrap();
-            coll.compute_aabb().center().into()
-        };
-
-        // Make a dummy image to get the sizing right
-        let lightmap = Image::gen_image_color(
-            (WIDTH / 16.0 * LIGHT_RESOLUTION) as u16,
-            (HEIGHT / 16.0 * LIGHT_RESOLUTION) as u16,
-            BLACK,
-        );
-        let light_tex = Texture2D::from_image(&lightmap);
-        light_tex.set_filter(FilterMode::Linear);
-
+        let level = LevelStreamer::new(seed, 0);
         ModeOverworld {
             world,
             physics,
-            camera_pos: center,
-            camera_target: center,
-            light_tex,
+            seed,
+            depth: 0,
+            ambient: level.ambient_light(),
+            level,
+            descent: None,
+            camera: Camera::new(),
+            layers: RenderLayers::new(),
+        }
+    }
+
+    /// Point the camera right at the player in the current level, without easing over.
+    fn snap_camera_to_player(&mut self) {
+        self.camera.set_focus(CameraFocus::Player);
+        self.camera.set_zoom(1.0);
+        self.camera.set_bounds(Some(self.level.bounds()));
+        self.camera.snap(&self.world, &self.physics);
+    }
+
+    /// Draw everything the camera can see, lit up, with the UI on top.
+    fn draw_frame(&self, assets: &Assets, controls: &InputSubscriber) {
+        use macroquad::prelude::*;
+
+        clear_background(BLACK);
+
+        // Everything the camera can see
+        let view = self.camera.view();
+
+        push_camera_state();
+        self.layers.begin(RenderLayer::World, view);
+        system_draw_tilemap(&self.world, assets, view);
+        system_draw_colored_boxes(&self.world, &self.physics);
+        system_draw_sprites(&self.world, &self.physics, assets);
+        system_draw_projectiles(&self.world, &self.physics);
+        system_draw_particles(&self.world, &self.physics);
+
+        // just do some debug drawing for now
+        if controls.pressed(Control::Debug) {
+            system_draw_collision(&self.world, &self.physics);
+            system_draw_light_polygons(&self.world, &self.physics);
         }
+
+        // Add up the lights in their own buffer, looking at the same place
+        system_draw_lights(
+            &self.world,
+            &self.physics,
+            assets,
+            &self.layers,
+            view,
+            self.ambient,
+        );
+
+        self.layers
+            .begin(RenderLayer::Ui, Rect::new(0.0, 0.0, WIDTH, HEIGHT));
+        system_draw_spellcaster(&self.world, controls);
+
+        pop_camera_state();
+
+        self.layers.composite(assets, view);
     }
 }
 
@@ -126,7 +190,46 @@ impl Gamemode for ModeOverworld {
         frame_info: FrameInfo,
         assets: &Assets,
     ) -> Transition {
+        if controls.clicked_down(Control::Pause) {
+            return Transition::Push(Box::new(ModePause::new(self.seed, self.depth)));
+        }
+        if controls.clicked_down(Control::Debug) {
+            info!("depth {}: {}", self.depth, self.level.biome_name());
+        }
+
+        let ambient_t = 1.0 - (-AMBIENT_SNAPPINESS * frame_info.dt).exp();
+        self.ambient = self.ambient.lerp(self.level.ambient_light(), ambient_t);
+
+        // Everything is on hold while we go down to the next level
+        if let Some(descent) = &mut self.descent {
+            // Check this first, so the camera snaps to where the player gets put
+            // on the last frame the world is hidden
+            let hidden = descent.is_hidden();
+            let done = descent.update(
+                frame_info.dt,
+                &mut self.world,
+                &mut self.physics,
+                &mut self.level,
+            );
+            if done {
+                self.descent = None;
+            }
+            if hidden {
+                self.snap_camera_to_player();
+            } else {
+                let dt = self.physics.integration_params.dt;
+                self.camera.update(&self.world, &self.physics, dt);
+            }
+            return Transition::None;
+        }
+
         system_player_inputs(&mut self.world, &mut self.physics, controls);
+        system_player_lantern(
+            &mut self.world,
+            &self.physics,
+            controls,
+            &self.camera.transform(),
+        );
         system_dazed(&mut self.world, &mut self.physics);
 
         system_run_physics(&mut self.world, &mut self.physics);
@@ -134,128 +237,59 @@ impl Gamemode for ModeOverworld {
         system_update_and_cleanup_projectiles(&mut self.world, &mut self.physics);
         system_cleanup_limited_timers(&mut self.world, &mut self.physics);
         system_cleanup_particles(&mut self.world, &mut self.physics);
+        system_camera_shake(&self.world, &self.physics, &mut self.camera);
         system_cleanup_explosions(&mut self.world, &mut self.physics);
         system_cleanup_dead(&mut self.world, &mut self.physics);
+        system_update_facing(&mut self.world, &self.physics);
+        system_animate_lights(&mut self.world, &self.physics);
+        system_player_animation(&mut self.world, &self.physics);
+        system_animate_sprites(&mut self.world, &self.physics, assets);
 
-        // To move the camera, we want
         if let Some(player_h) = self.world.get_player() {
-            let (coll_h, rb_h) = self
-                .world
-                .query_one_mut::<(&HasCollider, &HasRigidBody)>(player_h)
-                .unwrap();
-            let coll = self.physics.colliders.get(**coll_h).unwrap();
-            let rb = self.physics.rigid_bodies.get(**rb_h).unwrap();
-
-            let pos = coll.compute_aabb().center();
-            let vel = rb.linvel();
-
-            self.camera_target = (pos + vel * PLAYER_VEL_CAMERA_INFLUENCE).into();
+            let mut player = self.world.get_mut::<Player>(player_h).unwrap();
+            let entered = player.entered_shrine.take();
+            drop(player);
+
+            if let Some((shrine, level)) = entered {
+                self.depth = level;
+                self.descent = Some(Descent::new(self.seed, level));
+                // Go in for a closer look on the way down
+                self.camera.set_focus(CameraFocus::Entity(shrine));
+                self.camera.set_zoom(DESCENT_ZOOM);
+            }
         }
 
-        let cam_delta = self.camera_target - self.camera_pos;
-        if cam_delta.length_squared() > CAMERA_TOLERANCE * CAMERA_TOLERANCE {
-            let scaled = cam_delta * CAMERA_SNAPPINESS * self.physics.integration_params.dt;
-            self.camera_pos += scaled;
-        }
+        let dt = self.physics.integration_params.dt;
+        self.camera.update(&self.world, &self.physics, dt);
+
+        // Keep the chunks around the camera and where it's headed in the world
+        self.level.update(
+            &[self.camera.pos(), self.camera.target()],
+            COLLIDERS_PER_FRAME,
+            &mut self.world,
+            &mut self.physics,
+        );
 
         Transition::None
     }
 
-    fn draw(&self, assets: &Assets, frame_info: FrameInfo, controls: &InputSubscriber) {
-        use macroquad::prelude::*;
-
-        clear_background(BLACK);
-
-        let canvas = render_target(WIDTH as u32, HEIGHT as u32);
-        canvas.texture.set_filter(FilterMode::Nearest);
-
-        // Round the camera pos to the nearest 1/16 to prevent driftiness
-        let cam_x = (self.camera_pos.x * 16.0).round() / 16.0;
-        let cam_y = (self.camera_pos.y * 16.0).round() / 16.0;
-
-        push_camera_state();
-        let cam = Camera2D {
-            render_target: Some(canvas),
-            target: vec2(cam_x, cam_y),
-            zoom: vec2(2.0 / WIDTH, 2.0 / HEIGHT) * 16.0,
-            ..Default::default()
-        };
-        set_camera(&cam);
-
-        system_draw_colored_boxes(&self.world, &self.physics);
-        system_draw_projectiles(&self.world, &self.physics);
-        system_draw_particles(&self.world, &self.physics);
-
-        // just do some debug drawing for now
-        if controls.pressed(Control::Debug) {
-            system_draw_collision(&self.world, &self.physics);
+    fn draw(&self, assets: &Assets, _frame_info: FrameInfo, controls: &InputSubscriber) {
+        match &self.descent {
+            Some(descent) => descent.draw(|| self.draw_frame(assets, controls)),
+            None => self.draw_frame(assets, controls),
         }
+    }
 
-        // For now, do this terrible O(n^3) nonsense
-        let mut lightmap = Image::gen_image_color(
-            (WIDTH / 16.0 * LIGHT_RESOLUTION) as u16,
-            (HEIGHT / 16.0 * LIGHT_RESOLUTION) as u16,
-            BLACK,
-        );
-        for px in 0..lightmap.width() {
-            for py in 0..lightmap.height() {
-                let world_pos = self.camera_pos
-                    + vec2(
-                        // we add 0.5 to center the dots
-                        (px as f32 - lightmap.width() as f32 / 2.0 + 0.5) / LIGHT_RESOLUTION,
-                        (py as f32 - lightmap.height() as f32 / 2.0 + 0.5) / LIGHT_RESOLUTION,
-                    );
-                draw_circle(world_pos.x, world_pos.y, 0.1, WHITE);
-                let world_point: Point2<f32> = world_pos.into();
-
-                for (e, (coll_h, light)) in self
-                    .world
-                    .query::<(&HasCollider, &Illuminator)>()
-                    .into_iter()
-                {
-                    let coll = self.physics.colliders.get(**coll_h).unwrap();
-                    let lightpos = coll.compute_aabb().center();
-
-                    // Do we try to send light through anything?
-                    let delta = world_point - lightpos;
-                    let ray = Ray::new(lightpos, delta.normalize());
-                    let raycast = self.physics.query_pipeline.cast_ray(
-                        &self.physics.colliders,
-                        &ray,
-                        Real::MAX,
-                        false,
-                        InteractionGroups::new(
-                            collider_groups::GROUP_LIGHTING,
-                            collider_groups::FILTER_LIGHTING,
-                        ),
-                        None,
-                    );
-                    if raycast.is_none() || controls.pressed(Control::Debug) {
-                        // didn't hit anything on the way
-                        let color = light.get_color(lightpos.into(), world_pos);
-                        let existing = lightmap.get_pixel(px as u32, py as u32).to_vec();
-                        lightmap.set_pixel(
-                            px as u32,
-                            py as u32,
-                            Color::from_vec(color.extend(1.0) + existing),
-                        );
-                    }
-                }
+    fn on_resume(&mut self, _assets: &Assets) {
+        // Whatever the mouse did in the menu shouldn't count as drawing
+        if let Some(player_h) = self.world.get_player() {
+            let mut player = self.world.get_mut::<Player>(player_h).unwrap();
+            if let Some(caster) = &mut player.wip_spell {
+                caster.cancel_stroke();
             }
         }
-
-        pop_camera_state();
-
-        self.light_tex.update(&lightmap);
-        assets
-            .shaders
-            .lighting
-            .set_texture("lights", self.light_tex);
-        gl_use_material(assets.shaders.lighting);
-        draw_texture(canvas.texture, 0.0, 0.0, WHITE);
-        gl_use_default_material();
-
-        system_draw_spellcaster(&self.world, controls);
+        // Don't make the camera swoop over after sitting still
+        self.camera.snap(&self.world, &self.physics);
     }
 }
 
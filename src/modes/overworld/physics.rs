@@ -61,9 +61,12 @@ pub mod collider_groups {
     pub const GROUP_PROJECTILES: u32 = 0x00000004;
     /// The "group" for lighting calculations.
     pub const GROUP_LIGHTING: u32 = 0x00000008;
+    /// Sensors that do something when the player walks into them, like shrines.
+    pub const GROUP_INTERACTABLES: u32 = 0x00000010;
 
     pub const FILTER_WALLS: u32 = GROUP_ANIMATE | GROUP_PROJECTILES | GROUP_LIGHTING;
-    pub const FILTER_ANIMATE: u32 = GROUP_WALLS | GROUP_ANIMATE;
+    pub const FILTER_ANIMATE: u32 = GROUP_WALLS | GROUP_ANIMATE | GROUP_INTERACTABLES;
     pub const FILTER_PROJECTILES: u32 = GROUP_WALLS | GROUP_PROJECTILES;
     pub const FILTER_LIGHTING: u32 = GROUP_WALLS;
+    pub const FILTER_INTERACTABLES: u32 = GROUP_ANIMATE;
 }
@@ -0,0 +1,260 @@
+//! Biomes: what a level looks like and how it generates, depending on how deep it is.
+
+use macroquad::prelude::{vec3, Color, Vec3};
+
+use crate::utils::draw::hexcolor;
+
+use super::{
+    stages::{
+        BuildingStage, CaveStage, CrystalStage, FillStage, GenStage, PrefabStage, ValidateStage,
+    },
+    Tile,
+};
+
+/// Everything about how a level generates and looks.
+#[derive(Debug, Clone)]
+pub struct Biome {
+    pub name: &'static str,
+    /// The depth this biome was made for.
+    pub depth: u64,
+    /// How many chunks across and down levels are.
+    pub chunks: isize,
+    pub caves: CaveParams,
+    /// If this is None, no buildings get placed at all.
+    pub buildings: Option<BuildingParams>,
+    /// How many prefabs to try to put in each chunk.
+    pub prefab_count: usize,
+    /// If this is None, no crystals grow at all.
+    pub crystals: Option<CrystalParams>,
+    pub palette: Palette,
+    /// Light that's everywhere in the level, even where no lights reach.
+    pub ambient: AmbientLight,
+}
+
+/// Knobs for how the caves generate. These get meaner the deeper you go.
+#[derive(Debug, Clone, Copy)]
+pub struct CaveParams {
+    /// Hardness above which a tile turns to rock.
+    /// Lower means more rock and narrower caves.
+    pub rock_threshold: f32,
+    /// Rate of the exponential distribution the Growing Tree picks cells with.
+    /// Higher picks newer cells more often, making longer and twistier tunnels.
+    pub tree_twistiness: f32,
+    /// How strongly noise roughs up the carved caves.
+    pub noise_strength: f32,
+    pub billow_frequency: f64,
+    pub billow_octaves: usize,
+    /// How many tiles across one unit of noise is. Lower makes rougher caves.
+    pub noise_scale: f64,
+}
+
+/// Knobs for how buildings get placed.
+#[derive(Debug, Clone, Copy)]
+pub struct BuildingParams {
+    /// Most buildings there can be in one chunk.
+    pub max_buildings: usize,
+    /// Chance for each building to get an extra room stuck on the side.
+    pub annex_chance: f64,
+}
+
+/// Knobs for how glowing crystals get placed.
+#[derive(Debug, Clone, Copy)]
+pub struct CrystalParams {
+    /// Most crystals there can be in one chunk.
+    pub count: usize,
+    /// Color of the light they give off.
+    pub color: Vec3,
+}
+
+/// The light level everywhere in a level.
+#[derive(Debug, Clone, Copy)]
+pub struct AmbientLight {
+    pub color: Color,
+    /// How bright it is, from 0 (pitch black) to 1 (fully lit).
+    pub intensity: f32,
+}
+
+impl AmbientLight {
+    fn new(color: u32, intensity: f32) -> Self {
+        Self {
+            color: hexcolor(color),
+            intensity: intensity.clamp(0.0, 1.0),
+        }
+    }
+
+    /// The light this adds to everything, as an RGB vector.
+    pub fn light(&self) -> Vec3 {
+        vec3(self.color.r, self.color.g, self.color.b) * self.intensity
+    }
+}
+
+/// How each kind of tile looks in a biome.
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    pub ground: TileLook,
+    pub rock: TileLook,
+    pub wall: TileLook,
+    pub floor: TileLook,
+    pub path: TileLook,
+}
+
+impl Palette {
+    pub fn look(&self, tile: Tile) -> TileLook {
+        match tile {
+            Tile::Ground => self.ground,
+            Tile::Rock => self.rock,
+            Tile::Wall => self.wall,
+            Tile::Floor => self.floor,
+            Tile::Path => self.path,
+        }
+    }
+}
+
+/// Where to find a tile's texture, and what color it is on average.
+#[derive(Debug, Clone, Copy)]
+pub struct TileLook {
+    pub atlas: TileAtlas,
+    /// Which row of the atlas the tile's variants are on.
+    pub row: u8,
+    pub color: Color,
+}
+
+impl TileLook {
+    fn new(atlas: TileAtlas, row: u8, color: u32) -> Self {
+        Self {
+            atlas,
+            row,
+            color: hexcolor(color),
+        }
+    }
+}
+
+/// One of the textures in `assets::TileAtlases`.
+#[derive(Debug, Clone, Copy)]
+pub enum TileAtlas {
+    Ground,
+    GrassBuildings,
+    TerracottaGrids,
+}
+
+impl Biome {
+    /// Get the biome for the given depth.
+    pub fn for_depth(full_depth: u64) -> Self {
+        match full_depth {
+            0..=2 => {
+                let depth = full_depth as f32;
+                Biome {
+                    name: "Overgrown Caves",
+                    depth: full_depth,
+                    chunks: 4,
+                    prefab_count: 1,
+                    // Close enough to the surface that there's no need
+                    crystals: None,
+                    caves: CaveParams {
+                        rock_threshold: 0.3 - depth * 0.03,
+                        tree_twistiness: 0.5 + depth * 0.1,
+                        noise_strength: 1.0 + depth * 0.05,
+                        billow_frequency: 5.0,
+                        billow_octaves: 2,
+                        noise_scale: 40.0,
+                    },
+                    buildings: Some(BuildingParams {
+                        max_buildings: 2,
+                        annex_chance: 0.5,
+                    }),
+                    palette: Palette {
+                        ground: TileLook::new(TileAtlas::Ground, 1, 0x617464_ff),
+                        rock: TileLook::new(TileAtlas::Ground, 3, 0x4b3d50_ff),
+                        wall: TileLook::new(TileAtlas::GrassBuildings, 2, 0x615e4c_ff),
+                        floor: TileLook::new(TileAtlas::TerracottaGrids, 0, 0x735d53_ff),
+                        path: TileLook::new(TileAtlas::GrassBuildings, 1, 0x68755b_ff),
+                    },
+                    // Daylight still gets in up here
+                    ambient: AmbientLight::new(0xdcebcf_ff, 0.55 - depth * 0.15),
+                }
+            }
+            3..=5 => {
+                let depth = (full_depth - 3) as f32;
+                Biome {
+                    name: "Dusty Warrens",
+                    depth: full_depth,
+                    chunks: 6,
+                    prefab_count: 1,
+                    crystals: Some(CrystalParams {
+                        count: 2,
+                        color: vec3(1.0, 0.7, 0.35),
+                    }),
+                    caves: CaveParams {
+                        rock_threshold: 0.2 - depth * 0.03,
+                        tree_twistiness: 0.9 + depth * 0.1,
+                        noise_strength: 1.2 + depth * 0.05,
+                        billow_frequency: 3.0,
+                        billow_octaves: 3,
+                        noise_scale: 30.0,
+                    },
+                    buildings: Some(BuildingParams {
+                        max_buildings: 1,
+                        annex_chance: 0.8,
+                    }),
+                    palette: Palette {
+                        ground: TileLook::new(TileAtlas::Ground, 0, 0x767258_ff),
+                        rock: TileLook::new(TileAtlas::Ground, 3, 0x4b3d50_ff),
+                        wall: TileLook::new(TileAtlas::GrassBuildings, 2, 0x615e4c_ff),
+                        floor: TileLook::new(TileAtlas::TerracottaGrids, 1, 0x745b50_ff),
+                        path: TileLook::new(TileAtlas::TerracottaGrids, 2, 0x745749_ff),
+                    },
+                    ambient: AmbientLight::new(0xe3c9a1_ff, 0.15 - depth * 0.04),
+                }
+            }
+            _ => {
+                let depth = (full_depth - 6) as f32;
+                Biome {
+                    name: "Deep Hollows",
+                    depth: full_depth,
+                    chunks: 8,
+                    prefab_count: 1,
+                    crystals: Some(CrystalParams {
+                        count: 4,
+                        color: vec3(0.45, 0.6, 1.0),
+                    }),
+                    caves: CaveParams {
+                        rock_threshold: (0.1 - depth * 0.02).max(0.0),
+                        tree_twistiness: 1.5,
+                        noise_strength: 1.5,
+                        billow_frequency: 7.0,
+                        billow_octaves: 4,
+                        noise_scale: 24.0,
+                    },
+                    // nobody lives down here
+                    buildings: None,
+                    palette: Palette {
+                        ground: TileLook::new(TileAtlas::Ground, 2, 0x73798b_ff),
+                        rock: TileLook::new(TileAtlas::Ground, 3, 0x4b3d50_ff),
+                        wall: TileLook::new(TileAtlas::GrassBuildings, 2, 0x615e4c_ff),
+                        floor: TileLook::new(TileAtlas::TerracottaGrids, 2, 0x745749_ff),
+                        path: TileLook::new(TileAtlas::Ground, 0, 0x767258_ff),
+                    },
+                    // Only the crystals are bright down here
+                    ambient: AmbientLight::new(0x6f7fd6_ff, 0.05 - depth * 0.01),
+                }
+            }
+        }
+    }
+
+    /// Make the stages a level in this biome generates with, in order.
+    pub fn stages(&self) -> Vec<Box<dyn GenStage>> {
+        let mut stages: Vec<Box<dyn GenStage>> =
+            vec![Box::new(FillStage), Box::new(CaveStage::new())];
+        if self.buildings.is_some() {
+            stages.push(Box::new(BuildingStage));
+        }
+        if self.prefab_count > 0 {
+            stages.push(Box::new(PrefabStage));
+        }
+        if self.crystals.is_some() {
+            stages.push(Box::new(CrystalStage));
+        }
+        stages.push(Box::new(ValidateStage));
+        stages
+    }
+}
@@ -0,0 +1,447 @@
+//! Placing buildings: picking seeds in open ground, growing them out into rooms,
+//! punching doors in them, and linking them together with paths.
+
+use ahash::AHashMap;
+use cogs_gamedev::grids::{Direction4, ICoord};
+use rand::{seq::SliceRandom, Rng};
+
+use super::{biome::BuildingParams, Tile, TileMap, CHUNK_SIZE};
+
+/// How many random spots to try putting buildings at.
+const SEED_ATTEMPTS: usize = 200;
+/// Everything this close to a seed has to be open ground for it to be a good spot.
+const SEED_CLEARANCE: isize = 2;
+/// Buildings aren't allowed to get this close to the start or end.
+const ENDPOINT_CLEARANCE: isize = 8;
+/// Smallest a room can be across, walls included.
+const ROOM_MIN: isize = 5;
+/// Biggest a room can be across, walls included.
+const ROOM_MAX: isize = 12;
+/// The most of a new row of a growing room that can be rock.
+/// (It gets knocked down to make room.)
+const MAX_ROCK_RATIO: f32 = 0.3;
+/// Cost for paths to go through rock instead of around it.
+const PATH_ROCK_COST: u32 = 6;
+
+/// Rectangle of tiles, walls included. Both corners are inclusive.
+#[derive(Debug, Clone, Copy)]
+struct Room {
+    min: ICoord,
+    max: ICoord,
+}
+
+impl Room {
+    fn around(center: ICoord) -> Self {
+        Self {
+            min: center + ICoord::new(-1, -1),
+            max: center + ICoord::new(1, 1),
+        }
+    }
+
+    fn width(&self) -> isize {
+        self.max.x - self.min.x + 1
+    }
+
+    fn height(&self) -> isize {
+        self.max.y - self.min.y + 1
+    }
+
+    fn contains(&self, pos: ICoord) -> bool {
+        pos.x >= self.min.x && pos.x <= self.max.x && pos.y >= self.min.y && pos.y <= self.max.y
+    }
+
+    /// Is this inside the walls?
+    fn is_interior(&self, pos: ICoord) -> bool {
+        pos.x > self.min.x && pos.x < self.max.x && pos.y > self.min.y && pos.y < self.max.y
+    }
+
+    fn expanded(&self, by: isize) -> Room {
+        Room {
+            min: self.min + ICoord::new(-by, -by),
+            max: self.max + ICoord::new(by, by),
+        }
+    }
+
+    fn overlaps(&self, other: &Room) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+    }
+
+    /// Push one side of the room out by one tile.
+    fn grown(&self, dir: Direction4) -> Room {
+        let mut out = *self;
+        match dir {
+            Direction4::North => out.min.y -= 1,
+            Direction4::South => out.max.y += 1,
+            Direction4::West => out.min.x -= 1,
+            Direction4::East => out.max.x += 1,
+        }
+        out
+    }
+
+    /// The new row or column of tiles you'd get by growing in that direction.
+    fn strip(&self, dir: Direction4) -> Vec<ICoord> {
+        match dir {
+            Direction4::North | Direction4::South => {
+                let y = if let Direction4::North = dir {
+                    self.min.y - 1
+                } else {
+                    self.max.y + 1
+                };
+                (self.min.x..=self.max.x)
+                    .map(|x| ICoord::new(x, y))
+                    .collect()
+            }
+            Direction4::West | Direction4::East => {
+                let x = if let Direction4::West = dir {
+                    self.min.x - 1
+                } else {
+                    self.max.x + 1
+                };
+                (self.min.y..=self.max.y)
+                    .map(|y| ICoord::new(x, y))
+                    .collect()
+            }
+        }
+    }
+
+    fn positions(&self) -> impl Iterator<Item = ICoord> + '_ {
+        (self.min.x..=self.max.x)
+            .flat_map(move |x| (self.min.y..=self.max.y).map(move |y| ICoord::new(x, y)))
+    }
+}
+
+/// A building made of one or more overlapping rooms.
+/// Where rooms overlap, the walls between them get knocked out.
+struct Building {
+    rooms: Vec<Room>,
+    /// The tile just outside the door, where paths connect to.
+    doorstep: ICoord,
+    /// Tiles to set for this building.
+    tiles: AHashMap<ICoord, Tile>,
+}
+
+impl Building {
+    fn bounds(&self) -> Room {
+        let mut bounds = self.rooms[0];
+        for room in &self.rooms[1..] {
+            bounds.min.x = bounds.min.x.min(room.min.x);
+            bounds.min.y = bounds.min.y.min(room.min.y);
+            bounds.max.x = bounds.max.x.max(room.max.x);
+            bounds.max.y = bounds.max.y.max(room.max.y);
+        }
+        bounds
+    }
+}
+
+impl<R: Rng> TileMap<R> {
+    /// Put buildings down in open spaces and link them with paths.
+    pub(super) fn place_buildings(&mut self, params: &BuildingParams) {
+        let mut buildings: Vec<Building> = Vec::new();
+
+        for _ in 0..SEED_ATTEMPTS {
+            if buildings.len() >= params.max_buildings {
+                break;
+            }
+
+            let seed = ICoord::new(
+                self.rng.gen_range(ROOM_MAX..CHUNK_SIZE - ROOM_MAX),
+                self.rng.gen_range(ROOM_MAX..CHUNK_SIZE - ROOM_MAX),
+            );
+            if !self.is_good_seed(seed, &buildings) {
+                continue;
+            }
+
+            let main = self.grow_room(Room::around(seed), None, &buildings);
+            if main.width() < ROOM_MIN || main.height() < ROOM_MIN {
+                continue;
+            }
+            let mut rooms = vec![main];
+
+            // Sometimes stick another room on the side to make it less boxy
+            if self.rng.gen_bool(params.annex_chance) {
+                let side = *Direction4::DIRECTIONS.choose(&mut self.rng).unwrap();
+                let anchor = match side {
+                    Direction4::North => {
+                        ICoord::new(self.rng.gen_range(main.min.x..=main.max.x), main.min.y)
+                    }
+                    Direction4::South => {
+                        ICoord::new(self.rng.gen_range(main.min.x..=main.max.x), main.max.y)
+                    }
+                    Direction4::West => {
+                        ICoord::new(main.min.x, self.rng.gen_range(main.min.y..=main.max.y))
+                    }
+                    Direction4::East => {
+                        ICoord::new(main.max.x, self.rng.gen_range(main.min.y..=main.max.y))
+                    }
+                };
+                // Centering the annex on the wall makes it overlap the main room by 2,
+                // which knocks a hole in the wall between them.
+                let annex = self.grow_room(Room::around(anchor), Some(side), &buildings);
+                if annex.width() >= ROOM_MIN - 1 && annex.height() >= ROOM_MIN - 1 {
+                    rooms.push(annex);
+                }
+            }
+
+            if let Some(building) = self.lay_out_building(rooms) {
+                buildings.push(building);
+            }
+        }
+
+        for building in &buildings {
+            for (pos, tile) in &building.tiles {
+                self.tiles.insert(*pos, *tile);
+            }
+        }
+
+        self.link_buildings(&buildings);
+    }
+
+    /// Is this a nice open spot for a building, away from everything else?
+    fn is_good_seed(&self, seed: ICoord, buildings: &[Building]) -> bool {
+        let near = |a: ICoord, b: ICoord, dist: isize| {
+            (a.x - b.x).abs() <= dist && (a.y - b.y).abs() <= dist
+        };
+        if self
+            .anchors()
+            .iter()
+            .any(|&anchor| near(seed, anchor, ENDPOINT_CLEARANCE))
+        {
+            return false;
+        }
+        if buildings
+            .iter()
+            .any(|b| b.bounds().expanded(2).contains(seed))
+        {
+            return false;
+        }
+
+        (-SEED_CLEARANCE..=SEED_CLEARANCE).all(|dx| {
+            (-SEED_CLEARANCE..=SEED_CLEARANCE).all(|dy| {
+                matches!(
+                    self.tiles.get(&(seed + ICoord::new(dx, dy))),
+                    Some(Tile::Ground)
+                )
+            })
+        })
+    }
+
+    /// Grow the room out into open ground until it's as big as it wants to be,
+    /// or it runs out of space.
+    ///
+    /// If `away_from` is Some, it's an annex growing off the main room in that direction,
+    /// and it won't grow back towards it.
+    fn grow_room(
+        &mut self,
+        mut room: Room,
+        away_from: Option<Direction4>,
+        buildings: &[Building],
+    ) -> Room {
+        let target_width = self.rng.gen_range(ROOM_MIN..=ROOM_MAX);
+        let target_height = self.rng.gen_range(ROOM_MIN..=ROOM_MAX);
+
+        let mut dirs = Direction4::DIRECTIONS.to_vec();
+        if let Some(away) = away_from {
+            dirs.retain(|dir| *dir != away.flip());
+        }
+
+        loop {
+            dirs.shuffle(&mut self.rng);
+            let grew = dirs.iter().any(|&dir| {
+                let horizontal = matches!(dir, Direction4::East | Direction4::West);
+                if (horizontal && room.width() >= target_width)
+                    || (!horizontal && room.height() >= target_height)
+                {
+                    return false;
+                }
+
+                let grown = room.grown(dir);
+                if !self.can_build_on(&room.strip(dir), &grown, buildings) {
+                    return false;
+                }
+                room = grown;
+                true
+            });
+            if !grew {
+                return room;
+            }
+        }
+    }
+
+    /// Can these new tiles become part of a building?
+    fn can_build_on(&self, strip: &[ICoord], grown: &Room, buildings: &[Building]) -> bool {
+        if buildings
+            .iter()
+            .any(|b| b.rooms.iter().any(|r| r.expanded(1).overlaps(grown)))
+        {
+            return false;
+        }
+
+        let anchors = self.anchors();
+        let mut rock = 0;
+        for pos in strip {
+            if pos.x < 1 || pos.x >= CHUNK_SIZE - 1 || pos.y < 1 || pos.y >= CHUNK_SIZE - 1 {
+                return false;
+            }
+            if anchors.contains(pos) {
+                return false;
+            }
+            if self.tiles.get(pos).map_or(true, Tile::is_solid) {
+                rock += 1;
+            }
+        }
+        rock as f32 <= strip.len() as f32 * MAX_ROCK_RATIO
+    }
+
+    /// Work out which tiles are walls and floors, and punch a door in it.
+    ///
+    /// Returns None if there's nowhere to put a door.
+    fn lay_out_building(&mut self, rooms: Vec<Room>) -> Option<Building> {
+        let mut tiles = AHashMap::new();
+        for room in &rooms {
+            for pos in room.positions() {
+                let tile = if rooms.iter().any(|r| r.is_interior(pos)) {
+                    Tile::Floor
+                } else {
+                    Tile::Wall
+                };
+                tiles.insert(pos, tile);
+            }
+        }
+
+        // Doors go in walls with floor on one side and the outdoors on the other.
+        // Prefer putting them where there's open ground outside
+        let mut open_doors = Vec::new();
+        let mut rocky_doors = Vec::new();
+        let mut walls = tiles
+            .iter()
+            .filter(|(_, tile)| matches!(tile, Tile::Wall))
+            .map(|(pos, _)| *pos)
+            .collect::<Vec<_>>();
+        // the hashmap's order isn't stable, so sort it before picking
+        walls.sort_by_key(|pos| (pos.x, pos.y));
+        for pos in walls {
+            for dir in Direction4::DIRECTIONS {
+                let inside = pos + dir.flip();
+                let outside = pos + dir;
+                if matches!(tiles.get(&inside), Some(Tile::Floor)) && !tiles.contains_key(&outside)
+                {
+                    match self.tiles.get(&outside) {
+                        Some(tile) if !tile.is_solid() => open_doors.push((pos, outside)),
+                        Some(_) => rocky_doors.push((pos, outside)),
+                        None => {}
+                    }
+                }
+            }
+        }
+
+        let candidates = if open_doors.is_empty() {
+            rocky_doors
+        } else {
+            open_doors
+        };
+        let &(door, doorstep) = candidates.choose(&mut self.rng)?;
+        tiles.insert(door, Tile::Floor);
+
+        Some(Building {
+            rooms,
+            doorstep,
+            tiles,
+        })
+    }
+
+    /// Connect every building's door to the others with paths.
+    ///
+    /// Buildings are linked along a minimum spanning tree of their doors.
+    fn link_buildings(&mut self, buildings: &[Building]) {
+        if buildings.len() < 2 {
+            return;
+        }
+
+        let dist = |a: ICoord, b: ICoord| (a.x - b.x).abs() + (a.y - b.y).abs();
+
+        // Prim's algorithm
+        let mut connected = vec![0];
+        let mut unconnected = (1..buildings.len()).collect::<Vec<_>>();
+        while !unconnected.is_empty() {
+            let (from, to_idx) = connected
+                .iter()
+                .flat_map(|&c| {
+                    unconnected
+                        .iter()
+                        .enumerate()
+                        .map(move |(ui, &u)| (c, ui, u))
+                })
+                .min_by_key(|&(c, _, u)| dist(buildings[c].doorstep, buildings[u].doorstep))
+                .map(|(c, ui, _)| (c, ui))
+                .unwrap();
+            let to = unconnected.swap_remove(to_idx);
+
+            // Paths go around rock if they can, and never through buildings
+            let goal = buildings[to].doorstep;
+            let route = self.cheapest_route(
+                buildings[from].doorstep,
+                |pos| pos == goal,
+                |tile| match tile {
+                    Tile::Ground | Tile::Path => Some(1),
+                    Tile::Rock => Some(PATH_ROCK_COST),
+                    Tile::Wall | Tile::Floor => None,
+                },
+            );
+            for pos in route {
+                if matches!(self.tiles.get(&pos), Some(Tile::Ground) | Some(Tile::Rock)) {
+                    self.tiles.insert(pos, Tile::Path);
+                }
+            }
+            connected.push(to);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cogs_gamedev::grids::ICoord;
+
+    use super::super::{chunk_origin, LevelLayout, MapGenerator, Tile, CHUNK_SIZE};
+
+    fn generate_tiles(layout: &LevelLayout, chunk: ICoord) -> Vec<Tile> {
+        let mut generator = MapGenerator::new(layout, chunk);
+        while !generator.step() {}
+        let map = generator.finish();
+
+        let origin = chunk_origin(chunk);
+        (0..CHUNK_SIZE)
+            .flat_map(|y| (0..CHUNK_SIZE).map(move |x| origin + ICoord::new(x, y)))
+            .map(|pos| map.get(pos).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn same_chunk_gets_the_same_buildings() {
+        let mut saw_walls = false;
+        for seed in 0..4 {
+            // These depths all have buildings
+            for depth in 0..=2 {
+                let layout = LevelLayout::new(seed, depth);
+                for chunk in [ICoord::new(0, 0), ICoord::new(1, 2), ICoord::new(3, 1)] {
+                    let first = generate_tiles(&layout, chunk);
+                    let second = generate_tiles(&layout, chunk);
+                    assert!(
+                        first == second,
+                        "seed {} depth {} chunk {:?} generated differently twice",
+                        seed,
+                        depth,
+                        chunk
+                    );
+                    saw_walls |= first.contains(&Tile::Wall);
+                }
+            }
+        }
+        assert!(
+            saw_walls,
+            "no buildings got placed, so this didn't test anything"
+        );
+    }
+}
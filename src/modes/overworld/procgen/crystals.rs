@@ -0,0 +1,56 @@
+//! Growing glowing crystals out of cave walls, so the deep levels aren't totally dark.
+
+use cogs_gamedev::grids::{Direction4, ICoord};
+use rand::{seq::SliceRandom, Rng};
+
+use super::{biome::CrystalParams, Spawn, SpawnKind, Tile, TileMap, CHUNK_SIZE};
+
+/// Crystals aren't allowed to get this close to the start, end or gates.
+const ANCHOR_CLEARANCE: isize = 3;
+/// Crystals aren't allowed to get this close to each other.
+const CRYSTAL_SPACING: isize = 8;
+
+impl<R: Rng> TileMap<R> {
+    /// Put up to `params.count` crystals on open ground next to rock.
+    pub(super) fn place_crystals(&mut self, params: &CrystalParams) {
+        let near = |a: ICoord, b: ICoord, dist: isize| {
+            (a.x - b.x).abs() <= dist && (a.y - b.y).abs() <= dist
+        };
+        let anchors = self.anchors();
+        let mut spots = (0..CHUNK_SIZE)
+            .flat_map(|y| (0..CHUNK_SIZE).map(move |x| ICoord::new(x, y)))
+            .filter(|&pos| {
+                let open = matches!(self.tiles.get(&pos), Some(tile) if !tile.is_solid());
+                // They grow out of the rock, not in the middle of nowhere
+                let against_rock = Direction4::DIRECTIONS
+                    .iter()
+                    .any(|dir| matches!(self.tiles.get(&(pos + *dir)), Some(Tile::Rock)));
+                let clear = !anchors
+                    .iter()
+                    .any(|&anchor| near(anchor, pos, ANCHOR_CLEARANCE));
+                open && against_rock && clear
+            })
+            .collect::<Vec<_>>();
+        spots.shuffle(&mut self.rng);
+
+        let mut placed: Vec<ICoord> = Vec::new();
+        for pos in spots {
+            if placed.len() >= params.count {
+                break;
+            }
+            if !placed
+                .iter()
+                .any(|&other| near(other, pos, CRYSTAL_SPACING))
+            {
+                placed.push(pos);
+            }
+        }
+
+        self.spawns.extend(placed.into_iter().map(|pos| Spawn {
+            pos,
+            kind: SpawnKind::Crystal {
+                color: params.color,
+            },
+        }));
+    }
+}
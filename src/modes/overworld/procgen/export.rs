@@ -0,0 +1,285 @@
+//! Saving maps to text and pictures, and loading them back.
+//!
+//! The text format looks like this:
+//!
+//! ```text
+//! hexstudy-map 2
+//! seed 1234
+//! depth 0
+//! size 256
+//! start 3 60
+//! end 121 40
+//! spawn shrine 121 40 1
+//! spawn light 20 32 1.0 0.9 0.6
+//! spawn crystal 45 12 0.45 0.6 1.0
+//! tiles
+//! ################...
+//! ##....####::::W=...
+//! ```
+//!
+//! followed by `size` lines of `size` tiles each.
+
+use anyhow::{anyhow, bail, Context};
+use cogs_gamedev::grids::ICoord;
+use macroquad::prelude::{vec3, Image, BLACK, BLUE, ORANGE};
+
+use super::{biome::Biome, LevelMap, Spawn, SpawnKind, Tile};
+
+/// First line of every map file. Bump the number when the format changes.
+const HEADER: &str = "hexstudy-map 2";
+/// How many pixels across each tile is in a preview picture.
+const PREVIEW_SCALE: u32 = 4;
+
+impl Tile {
+    pub(super) fn to_char(self) -> char {
+        match self {
+            Tile::Ground => '.',
+            Tile::Rock => '#',
+            Tile::Wall => 'W',
+            Tile::Floor => ':',
+            Tile::Path => '=',
+        }
+    }
+
+    pub(super) fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            '.' => Tile::Ground,
+            '#' => Tile::Rock,
+            'W' => Tile::Wall,
+            ':' => Tile::Floor,
+            '=' => Tile::Path,
+            _ => return None,
+        })
+    }
+}
+
+impl LevelMap {
+    /// Write the map out in the text format.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        out.push_str(HEADER);
+        out.push('\n');
+        out.push_str(&format!("seed {}\n", self.seed));
+        out.push_str(&format!("depth {}\n", self.depth));
+        out.push_str(&format!("size {}\n", self.size));
+        out.push_str(&format!(
+            "start {} {}\n",
+            self.start_pos.x, self.start_pos.y
+        ));
+        out.push_str(&format!("end {} {}\n", self.end_pos.x, self.end_pos.y));
+        for spawn in &self.spawns {
+            match spawn.kind {
+                SpawnKind::Shrine { level } => out.push_str(&format!(
+                    "spawn shrine {} {} {}\n",
+                    spawn.pos.x, spawn.pos.y, level
+                )),
+                SpawnKind::Light { color } => out.push_str(&format!(
+                    "spawn light {} {} {} {} {}\n",
+                    spawn.pos.x, spawn.pos.y, color.x, color.y, color.z
+                )),
+                SpawnKind::Crystal { color } => out.push_str(&format!(
+                    "spawn crystal {} {} {} {} {}\n",
+                    spawn.pos.x, spawn.pos.y, color.x, color.y, color.z
+                )),
+            }
+        }
+
+        out.push_str("tiles\n");
+        for row in self.tiles.chunks(self.size as usize) {
+            out.extend(row.iter().map(|tile| tile.to_char()));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Read a map written with `to_text`.
+    pub fn from_text(text: &str) -> anyhow::Result<Self> {
+        let mut lines = text.lines().map(str::trim_end).enumerate();
+
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            Some((_, other)) => bail!("expected header `{}`, found `{}`", HEADER, other),
+            None => bail!("map file is empty"),
+        }
+
+        let mut seed = None;
+        let mut depth = None;
+        let mut size = None;
+        let mut start_pos = None;
+        let mut end_pos = None;
+        let mut spawns = Vec::new();
+
+        for (line_num, line) in &mut lines {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let word = |idx: usize| -> anyhow::Result<&str> {
+                words
+                    .get(idx)
+                    .copied()
+                    .ok_or_else(|| anyhow!("line {}: missing a number", line_num + 1))
+            };
+            let number = |idx: usize| -> anyhow::Result<i64> {
+                let word = word(idx)?;
+                word.parse()
+                    .with_context(|| format!("line {}: bad number `{}`", line_num + 1, word))
+            };
+            // Seeds use the whole range of a u64, so they can't go through an i64
+            let unsigned = |idx: usize| -> anyhow::Result<u64> {
+                let word = word(idx)?;
+                word.parse()
+                    .with_context(|| format!("line {}: bad number `{}`", line_num + 1, word))
+            };
+            let float = |idx: usize| -> anyhow::Result<f32> {
+                let word = word(idx)?;
+                word.parse()
+                    .with_context(|| format!("line {}: bad number `{}`", line_num + 1, word))
+            };
+            let coord = |idx: usize| -> anyhow::Result<ICoord> {
+                Ok(ICoord::new(
+                    number(idx)? as isize,
+                    number(idx + 1)? as isize,
+                ))
+            };
+
+            match words.as_slice() {
+                ["seed", ..] => seed = Some(unsigned(1)?),
+                ["depth", ..] => depth = Some(unsigned(1)?),
+                ["size", ..] => size = Some(number(1)? as isize),
+                ["start", ..] => start_pos = Some(coord(1)?),
+                ["end", ..] => end_pos = Some(coord(1)?),
+                ["spawn", "shrine", ..] => spawns.push(Spawn {
+                    pos: coord(2)?,
+                    kind: SpawnKind::Shrine {
+                        level: unsigned(4)?,
+                    },
+                }),
+                ["spawn", "light", ..] => spawns.push(Spawn {
+                    pos: coord(2)?,
+                    kind: SpawnKind::Light {
+                        color: vec3(float(4)?, float(5)?, float(6)?),
+                    },
+                }),
+                ["spawn", "crystal", ..] => spawns.push(Spawn {
+                    pos: coord(2)?,
+                    kind: SpawnKind::Crystal {
+                        color: vec3(float(4)?, float(5)?, float(6)?),
+                    },
+                }),
+                ["tiles"] => break,
+                _ => bail!("line {}: don't know what `{}` means", line_num + 1, line),
+            }
+        }
+
+        let size = size.context("missing size")?;
+        let mut tiles = Vec::with_capacity((size * size) as usize);
+        for (line_num, line) in lines.take(size as usize) {
+            if line.chars().count() != size as usize {
+                bail!(
+                    "line {}: expected {} tiles, found {}",
+                    line_num + 1,
+                    size,
+                    line.chars().count()
+                );
+            }
+            for c in line.chars() {
+                let tile = Tile::from_char(c)
+                    .ok_or_else(|| anyhow!("line {}: unknown tile `{}`", line_num + 1, c))?;
+                tiles.push(tile);
+            }
+        }
+        if tiles.len() != (size * size) as usize {
+            bail!("expected {} rows of tiles", size);
+        }
+
+        let depth = depth.context("missing depth")?;
+        Ok(LevelMap {
+            tiles,
+            size,
+            start_pos: start_pos.context("missing start")?,
+            end_pos: end_pos.context("missing end")?,
+            spawns,
+            seed: seed.context("missing seed")?,
+            depth,
+            palette: Biome::for_depth(depth).palette,
+        })
+    }
+
+    /// Draw the map into a picture, with the start in orange and the end in blue.
+    pub fn to_image(&self) -> Image {
+        let pixels = (self.size as u32 * PREVIEW_SCALE) as u16;
+        let mut image = Image::gen_image_color(pixels, pixels, BLACK);
+
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let pos = ICoord::new(x, y);
+                let color = if pos == self.start_pos {
+                    ORANGE
+                } else if pos == self.end_pos {
+                    BLUE
+                } else {
+                    self.palette.look(self.get(pos).unwrap()).color
+                };
+
+                for py in 0..PREVIEW_SCALE {
+                    for px in 0..PREVIEW_SCALE {
+                        image.set_pixel(
+                            x as u32 * PREVIEW_SCALE + px,
+                            y as u32 * PREVIEW_SCALE + py,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::LevelMap;
+
+    /// A level saved with `--map-preview 1234 0`.
+    /// If the generator changes on purpose, save it again.
+    const GOLDEN: &str = include_str!("golden/seed_1234_depth_0.txt");
+
+    /// Find the first line that's different, and what's on it in each.
+    fn first_difference<'a>(want: &'a str, got: &'a str) -> Option<(usize, &'a str, &'a str)> {
+        let (mut want_lines, mut got_lines) = (want.lines(), got.lines());
+        for line_num in 1.. {
+            match (want_lines.next(), got_lines.next()) {
+                (None, None) => return None,
+                (want, got) if want != got => {
+                    return Some((line_num, want.unwrap_or("<end>"), got.unwrap_or("<end>")))
+                }
+                _ => {}
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn export_import_export_round_trip() {
+        // This depth has crystals, so there are colors to read back too
+        let text = LevelMap::generate(77, 3).to_text();
+        let imported = LevelMap::from_text(&text).unwrap();
+        assert_eq!(first_difference(&text, &imported.to_text()), None);
+    }
+
+    #[test]
+    fn seeds_past_i64_round_trip() {
+        let text = LevelMap::generate(u64::MAX, 0).to_text();
+        let imported = LevelMap::from_text(&text).unwrap();
+        assert_eq!(imported.seed, u64::MAX);
+        assert_eq!(first_difference(&text, &imported.to_text()), None);
+    }
+
+    #[test]
+    fn generating_matches_golden() {
+        let golden = LevelMap::from_text(GOLDEN).unwrap();
+        assert_eq!(first_difference(GOLDEN, &golden.to_text()), None);
+
+        let generated = LevelMap::generate(golden.seed, golden.depth).to_text();
+        assert_eq!(first_difference(GOLDEN, &generated), None);
+    }
+}
@@ -0,0 +1,134 @@
+//! Generating chunks without freezing the game.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use cogs_gamedev::grids::ICoord;
+
+use super::{ChunkMap, LevelLayout, MapGenerator};
+
+/// How long to spend generating per frame when we can't use threads, in seconds.
+#[cfg(target_arch = "wasm32")]
+const INCREMENTAL_BUDGET: f64 = 1.0 / 120.0;
+
+/// A chunk being generated in the background.
+///
+/// On native this runs on its own thread. On wasm (where there are no threads)
+/// it generates a bit every time it's polled instead.
+/// Dropping it calls the job off.
+pub struct MapJob {
+    inner: JobInner,
+    progress: f32,
+    description: &'static str,
+}
+
+enum JobInner {
+    #[cfg(not(target_arch = "wasm32"))]
+    Threaded(Receiver<JobUpdate>),
+    #[cfg(target_arch = "wasm32")]
+    Incremental(Option<MapGenerator>),
+}
+
+/// Messages from the generator thread
+#[cfg(not(target_arch = "wasm32"))]
+enum JobUpdate {
+    Progress(f32, &'static str),
+    Done(ChunkMap),
+}
+
+impl MapJob {
+    /// Start generating the given chunk of the level.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start(layout: LevelLayout, chunk: ICoord) -> Self {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut generator = MapGenerator::new(&layout, chunk);
+            while !generator.step() {
+                // If this fails, nobody is waiting for this anymore
+                if tx
+                    .send(JobUpdate::Progress(
+                        generator.progress(),
+                        generator.description(),
+                    ))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            let _ = tx.send(JobUpdate::Done(generator.finish()));
+        });
+
+        Self {
+            inner: JobInner::Threaded(rx),
+            progress: 0.0,
+            description: "",
+        }
+    }
+
+    /// Start generating the given chunk of the level.
+    #[cfg(target_arch = "wasm32")]
+    pub fn start(layout: LevelLayout, chunk: ICoord) -> Self {
+        Self {
+            inner: JobInner::Incremental(Some(MapGenerator::new(&layout, chunk))),
+            progress: 0.0,
+            description: "",
+        }
+    }
+
+    /// Check up on the job. Returns the chunk once it's done.
+    ///
+    /// After this has returned Some, it will never return anything again.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll(&mut self) -> Option<ChunkMap> {
+        let JobInner::Threaded(rx) = &self.inner;
+        loop {
+            match rx.try_recv() {
+                Ok(JobUpdate::Progress(progress, description)) => {
+                    self.progress = progress;
+                    self.description = description;
+                }
+                Ok(JobUpdate::Done(chunk)) => {
+                    self.progress = 1.0;
+                    return Some(chunk);
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    panic!("chunk generator thread died without finishing")
+                }
+            }
+        }
+    }
+
+    /// Check up on the job. Returns the chunk once it's done.
+    ///
+    /// After this has returned Some, it will never return anything again.
+    #[cfg(target_arch = "wasm32")]
+    pub fn poll(&mut self) -> Option<ChunkMap> {
+        let JobInner::Incremental(slot) = &mut self.inner;
+        let generator = slot.as_mut()?;
+
+        let start = macroquad::time::get_time();
+        let mut done = false;
+        while !done && macroquad::time::get_time() - start < INCREMENTAL_BUDGET {
+            done = generator.step();
+        }
+        self.progress = generator.progress();
+        self.description = generator.description();
+
+        if done {
+            slot.take().map(MapGenerator::finish)
+        } else {
+            None
+        }
+    }
+
+    /// Roughly how much of the chunk is generated, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        self.progress
+    }
+
+    /// What the generator is doing right now.
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+}
@@ -1,39 +1,90 @@
 //! RNG and procedural generation.
-
-use std::convert::TryInto;
+//!
+//! Levels are split into square chunks, `CHUNK_SIZE` tiles on a side.
+//! Each chunk is generated on its own from the seed, the depth and where it is,
+//! so they can be made in any order as the player wanders around.
+//! Neighboring chunks line up by sharing gates: tiles on both sides of the border
+//! that both chunks keep open and connected.
+
+mod biome;
+mod buildings;
+mod crystals;
+mod export;
+mod job;
+mod prefab;
+mod preview;
+mod stages;
+mod stream;
+mod validate;
+pub use biome::{Palette, TileAtlas};
+pub use job::MapJob;
+pub use preview::run_map_tool;
+pub use stream::{clear_level, LevelStreamer, COLLIDERS_PER_FRAME};
+
+use std::{cmp::Reverse, collections::BinaryHeap, convert::TryInto};
 
 use ahash::{AHashMap, AHashSet};
 use cogs_gamedev::grids::{Direction4, ICoord};
-use hecs::World;
-use macroquad::prelude::{Color, BLUE};
-use nalgebra::vector;
+use macroquad::prelude::{vec2, warn, Vec2, Vec3};
 use noise::{Billow, Blend, NoiseFn, ScaleBias, Seedable, SuperSimplex};
 use rand::{Rng, SeedableRng};
 use rand_xoshiro::Xoshiro256StarStar;
-use rapier2d::{
-    prelude::{ColliderBuilder, InteractionGroups, RigidBodyBuilder},
-    utils::WSign,
-};
 
-use crate::{
-    modes::overworld::{
-        cs::{colored_box::ColoredBox, physics::HasRigidBody, shrine::Shrine},
-        physics::collider_groups,
-    },
-    utils::draw::hexcolor,
+use self::{
+    biome::{Biome, CaveParams},
+    stages::GenStage,
+    validate::MapReport,
 };
 
-use super::{cs::player::Player, physics::PhysicsWorld, WorldExt};
-
-/// Tile size in the world.
-pub const WORLD_SIZE: isize = 128;
+/// How many tiles across and down each chunk of a level is.
+pub const CHUNK_SIZE: isize = 64;
 /// How many physics units one tile corresponds to.
 pub const TILE_SCALE: f32 = 2.0;
 
+/// How many times to start a chunk over if it comes out unsolvable before giving up on it.
+const MAX_ATTEMPTS: u64 = 4;
+
+/// How many gates there are on each border between two chunks.
+const GATES_PER_BORDER: usize = 2;
+/// Gates stay at least this far away from the corners of their chunk.
+const GATE_MARGIN: isize = 4;
+
+/// Salts for `level_rng`, so chunks and the borders between them don't share rngs.
+const SALT_CHUNK: u64 = 1;
+const SALT_EAST_BORDER: u64 = 2;
+const SALT_SOUTH_BORDER: u64 = 3;
+
+/// Get the center of the given tile in world (physics) coordinates.
+pub fn tile_to_world(pos: ICoord) -> Vec2 {
+    vec2(pos.x as f32, pos.y as f32) * TILE_SCALE
+}
+
+/// Get which tile the given world (physics) coordinate is in.
+///
+/// Tiles are centered on `tile_to_world` of their position,
+/// so this rounds to the nearest tile center rather than flooring.
+pub fn world_to_tile(pos: Vec2) -> ICoord {
+    let scaled = pos / TILE_SCALE;
+    ICoord::new(scaled.x.round() as isize, scaled.y.round() as isize)
+}
+
+/// Get which chunk the given tile is in.
+pub fn tile_to_chunk(pos: ICoord) -> ICoord {
+    ICoord::new(pos.x.div_euclid(CHUNK_SIZE), pos.y.div_euclid(CHUNK_SIZE))
+}
+
+/// Get the top-left tile of the given chunk.
+pub fn chunk_origin(chunk: ICoord) -> ICoord {
+    ICoord::new(chunk.x * CHUNK_SIZE, chunk.y * CHUNK_SIZE)
+}
+
+/// How many cells the Growing Tree carver looks at per generation step.
+const TREE_CELLS_PER_STEP: usize = 2048;
+
 /// Abstraction layer over components: tiles representing structures.
 /// These are 1x1 meters, or 16x16 pixels.
-#[derive(Debug, Clone, Copy)]
-enum Tile {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tile {
     /// Normal ground
     Ground,
     /// Solid blocks that weren't created by people
@@ -47,27 +98,19 @@ enum Tile {
 }
 
 impl Tile {
-    fn is_solid(&self) -> bool {
+    pub fn is_solid(&self) -> bool {
         match self {
             Tile::Ground | Tile::Floor | Tile::Path => false,
             Tile::Rock | Tile::Wall => true,
         }
     }
-
-    fn color(&self) -> Color {
-        match self {
-            Tile::Ground => hexcolor(0x617464_ff),
-            Tile::Rock => hexcolor(0x545246_ff),
-            Tile::Wall => hexcolor(0x615e4c_ff),
-            Tile::Floor => hexcolor(0x7e8c6d_ff),
-            Tile::Path => hexcolor(0x6b786d_ff),
-        }
-    }
 }
 
-/// Remove all entities except for the player, generate a map at the given depth,
-/// and add the new things to the world.
-pub fn generate_map(seed: u64, depth: u64, world: &mut World, physics: &mut PhysicsWorld) {
+/// Make an rng for some part of a level.
+///
+/// `salt` says which part; the same seed, depth and salt always make the same rng.
+/// The level as a whole uses a salt of 0.
+fn level_rng(seed: u64, depth: u64, salt: u64) -> Xoshiro256StarStar {
     // From the rand docs:
     // PRNGs: Several companion crates are available,
     // providing individual or families of PRNG algorithms.
@@ -85,118 +128,569 @@ pub fn generate_map(seed: u64, depth: u64, world: &mut World, physics: &mut Phys
     // so i do a little mixing.
     // Hope this is OK
     let seed_split = [
-        seed.to_le_bytes(),
+        (seed ^ salt).to_le_bytes(),
         depth.to_le_bytes(),
         (!depth).to_be_bytes(),
-        (!seed).to_be_bytes(),
+        (!seed ^ salt.rotate_left(32)).to_be_bytes(),
     ];
     let rng_seed = seed_split.concat();
-    let mut rng = Xoshiro256StarStar::from_seed(rng_seed.try_into().unwrap());
-
-    let start_pos = ICoord::new(
-        rng.gen_range(0..WORLD_SIZE / 10),
-        rng.gen_range(0..WORLD_SIZE),
-    );
-    let end_pos = ICoord::new(
-        rng.gen_range(WORLD_SIZE * 9 / 10..WORLD_SIZE),
-        rng.gen_range(0..WORLD_SIZE),
-    );
-
-    let mut state = TileMap {
-        tiles: AHashMap::new(),
-        start_pos,
-        end_pos,
-        rng,
-    };
-
-    // Remove everything but the player
-    {
-        let mut remove = Vec::new();
-        for (e, player) in world.query_mut::<Option<&Player>>() {
-            if player.is_none() {
-                remove.push(e);
+    Xoshiro256StarStar::from_seed(rng_seed.try_into().unwrap())
+}
+
+/// Salt for `level_rng` for something to do with the given chunk.
+fn chunk_salt(kind: u64, chunk: ICoord) -> u64 {
+    scramble(kind ^ scramble(chunk.x as u64 ^ scramble(chunk.y as u64)))
+}
+
+/// Mix up the bits of a number so similar inputs come out very different.
+/// This is SplitMix64's finalizer.
+fn scramble(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9e3779b97f4a7c15);
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// The overall shape of a level: how many chunks it has and where it starts and ends.
+///
+/// This is quick to work out, so everything that needs one can just make its own.
+#[derive(Debug, Clone)]
+pub struct LevelLayout {
+    seed: u64,
+    depth: u64,
+    biome: Biome,
+    /// Where the player starts, in level tile coordinates.
+    start_pos: ICoord,
+    /// Where the exit shrine goes, in level tile coordinates.
+    /// Prefabs can move this around inside its chunk.
+    end_pos: ICoord,
+    /// Seed for the noise that weathers the caves.
+    /// The whole level shares it so the rock lines up across chunk borders.
+    noise_seed: u32,
+}
+
+impl LevelLayout {
+    pub fn new(seed: u64, depth: u64) -> Self {
+        let mut rng = level_rng(seed, depth, 0);
+        let biome = Biome::for_depth(depth);
+        let size = biome.chunks * CHUNK_SIZE;
+
+        let start_pos = ICoord::new(rng.gen_range(0..size / 10), rng.gen_range(0..size));
+        let end_pos = ICoord::new(rng.gen_range(size * 9 / 10..size), rng.gen_range(0..size));
+        let noise_seed = rng.gen();
+
+        Self {
+            seed,
+            depth,
+            biome,
+            start_pos,
+            end_pos,
+            noise_seed,
+        }
+    }
+
+    pub fn biome(&self) -> &Biome {
+        &self.biome
+    }
+
+    pub fn start_pos(&self) -> ICoord {
+        self.start_pos
+    }
+
+    /// How many tiles across and down the whole level is.
+    pub fn size(&self) -> isize {
+        self.biome.chunks * CHUNK_SIZE
+    }
+
+    /// Is there a chunk at the given chunk position?
+    pub fn has_chunk(&self, chunk: ICoord) -> bool {
+        let range = 0..self.biome.chunks;
+        range.contains(&chunk.x) && range.contains(&chunk.y)
+    }
+
+    /// Get the gates along the edges of the given chunk, in level tile coordinates.
+    fn gates(&self, chunk: ICoord) -> Vec<ICoord> {
+        let origin = chunk_origin(chunk);
+        let mut gates = Vec::new();
+
+        for dir in Direction4::DIRECTIONS {
+            let other = chunk + dir;
+            if !self.has_chunk(other) {
+                continue;
+            }
+
+            // Both chunks have to agree on where the gates go,
+            // so the border is named after the chunk to the west or north of it.
+            let (owner, kind) = match dir {
+                Direction4::East => (chunk, SALT_EAST_BORDER),
+                Direction4::West => (other, SALT_EAST_BORDER),
+                Direction4::South => (chunk, SALT_SOUTH_BORDER),
+                Direction4::North => (other, SALT_SOUTH_BORDER),
+            };
+            let mut rng = level_rng(self.seed, self.depth, chunk_salt(kind, owner));
+            for _ in 0..GATES_PER_BORDER {
+                let along = rng.gen_range(GATE_MARGIN..CHUNK_SIZE - GATE_MARGIN);
+                let offset = match dir {
+                    Direction4::East => ICoord::new(CHUNK_SIZE - 1, along),
+                    Direction4::West => ICoord::new(0, along),
+                    Direction4::South => ICoord::new(along, CHUNK_SIZE - 1),
+                    Direction4::North => ICoord::new(along, 0),
+                };
+                gates.push(origin + offset);
             }
         }
-        for e in remove {
-            world.despawn_with_physics(physics, e).unwrap();
+
+        gates
+    }
+}
+
+/// One finished chunk of a level, ready to be put in the world.
+#[derive(Clone)]
+pub struct ChunkMap {
+    /// Which chunk this is, in chunk coordinates.
+    coord: ICoord,
+    /// Row-major tiles, `CHUNK_SIZE` on a side.
+    tiles: Vec<Tile>,
+    /// Things to put in the world along with the tiles.
+    spawns: Vec<Spawn>,
+    /// Where the exit is, if it's in this chunk.
+    end_pos: Option<ICoord>,
+}
+
+/// Something that gets put in the world when a chunk is loaded.
+#[derive(Debug, Clone)]
+pub struct Spawn {
+    /// Where it goes, in level tile coordinates.
+    pub pos: ICoord,
+    pub kind: SpawnKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum SpawnKind {
+    /// Shrine leading to the given level.
+    Shrine { level: u64 },
+    /// A light that just sits there.
+    Light { color: Vec3 },
+    /// A glowing crystal growing out of the rock.
+    Crystal { color: Vec3 },
+}
+
+impl ChunkMap {
+    /// Which chunk this is, in chunk coordinates.
+    pub fn coord(&self) -> ICoord {
+        self.coord
+    }
+
+    /// Get the tile at the given position in level tile coordinates,
+    /// or None if it's not in this chunk.
+    pub fn get(&self, pos: ICoord) -> Option<Tile> {
+        let origin = chunk_origin(self.coord);
+        let (x, y) = (pos.x - origin.x, pos.y - origin.y);
+        if (0..CHUNK_SIZE).contains(&x) && (0..CHUNK_SIZE).contains(&y) {
+            Some(self.tiles[(y * CHUNK_SIZE + x) as usize])
+        } else {
+            None
         }
     }
 
-    state.generate();
+    pub fn spawns(&self) -> &[Spawn] {
+        &self.spawns
+    }
 
-    for y in 0..WORLD_SIZE {
-        let line = (0..WORLD_SIZE)
-            .map(|x| {
-                let pos = ICoord::new(x, y);
-                let tile = state.tiles.get(&pos).unwrap();
-                if tile.is_solid() {
-                    '#'
-                } else {
-                    ' '
+    /// Merge all the solid tiles into as few rectangles as possible,
+    /// so they don't each need their own collider.
+    /// The rectangles are in level tile coordinates.
+    ///
+    /// This goes greedily: each rectangle is grown as far right as it can,
+    /// then as far down as the whole row allows.
+    fn solid_rects(&self) -> Vec<SolidRect> {
+        let mut used = vec![false; self.tiles.len()];
+        let idx = |x: isize, y: isize| (y * CHUNK_SIZE + x) as usize;
+        let free_solid = |used: &[bool], x: isize, y: isize| {
+            !used[idx(x, y)] && self.tiles[idx(x, y)].is_solid()
+        };
+
+        let origin = chunk_origin(self.coord);
+        let mut rects = Vec::new();
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                if !free_solid(&used, x, y) {
+                    continue;
                 }
-            })
-            .collect::<String>();
-        println!("{}", &line);
+
+                let mut width = 1;
+                while x + width < CHUNK_SIZE && free_solid(&used, x + width, y) {
+                    width += 1;
+                }
+                let mut height = 1;
+                while y + height < CHUNK_SIZE
+                    && (x..x + width).all(|rx| free_solid(&used, rx, y + height))
+                {
+                    height += 1;
+                }
+
+                for ry in y..y + height {
+                    for rx in x..x + width {
+                        used[idx(rx, ry)] = true;
+                    }
+                }
+                rects.push(SolidRect {
+                    min: origin + ICoord::new(x, y),
+                    width,
+                    height,
+                });
+            }
+        }
+        rects
+    }
+}
+
+/// A whole level, stitched together from all its chunks.
+///
+/// The game never needs all of a level at once; this is for the map tools.
+#[derive(Clone)]
+pub struct LevelMap {
+    /// Row-major tiles, `size` on a side.
+    tiles: Vec<Tile>,
+    size: isize,
+    start_pos: ICoord,
+    end_pos: ICoord,
+    /// Things to put in the world along with the tiles.
+    spawns: Vec<Spawn>,
+    /// Seed this was generated from.
+    seed: u64,
+    depth: u64,
+    palette: Palette,
+}
+
+impl LevelMap {
+    /// Generate every chunk of a level and stitch them together.
+    pub fn generate(seed: u64, depth: u64) -> Self {
+        let layout = LevelLayout::new(seed, depth);
+        let size = layout.size();
+        let mut tiles = vec![Tile::Rock; (size * size) as usize];
+        let mut end_pos = layout.end_pos;
+        let mut spawns = Vec::new();
+
+        for cy in 0..layout.biome.chunks {
+            for cx in 0..layout.biome.chunks {
+                let mut generator = MapGenerator::new(&layout, ICoord::new(cx, cy));
+                while !generator.step() {}
+                let chunk = generator.finish();
+
+                let origin = chunk_origin(chunk.coord);
+                for (idx, tile) in chunk.tiles.iter().enumerate() {
+                    let x = origin.x + idx as isize % CHUNK_SIZE;
+                    let y = origin.y + idx as isize / CHUNK_SIZE;
+                    tiles[(y * size + x) as usize] = *tile;
+                }
+                if let Some(pos) = chunk.end_pos {
+                    end_pos = pos;
+                }
+                spawns.extend(chunk.spawns);
+            }
+        }
+
+        LevelMap {
+            tiles,
+            size,
+            start_pos: layout.start_pos,
+            end_pos,
+            spawns,
+            seed,
+            depth,
+            palette: layout.biome.palette,
+        }
     }
 
-    for (pos, tile) in state.tiles {
-        let color = tile.color();
-        let filter = if tile.is_solid() {
-            collider_groups::FILTER_WALLS
+    /// Get the tile at the given position, or None if it's out of bounds.
+    pub fn get(&self, pos: ICoord) -> Option<Tile> {
+        if pos.x >= 0 && pos.x < self.size && pos.y >= 0 && pos.y < self.size {
+            Some(self.tiles[(pos.y * self.size + pos.x) as usize])
         } else {
-            0x0
+            None
+        }
+    }
+}
+
+/// Makes one `ChunkMap` a little bit at a time.
+///
+/// Call `step` until it returns `true`, then `finish` it.
+pub struct MapGenerator {
+    state: TileMap<Xoshiro256StarStar>,
+    layout: LevelLayout,
+    stages: Vec<Box<dyn GenStage>>,
+    /// Index of the stage we're on
+    current: usize,
+    /// Which chunk we're making
+    coord: ICoord,
+    /// How many times the chunk has been started over because it couldn't be solved
+    attempt: u64,
+}
+
+impl MapGenerator {
+    pub fn new(layout: &LevelLayout, coord: ICoord) -> Self {
+        Self {
+            state: Self::fresh_state(layout, coord, 0),
+            layout: layout.clone(),
+            stages: layout.biome.stages(),
+            current: 0,
+            coord,
+            attempt: 0,
+        }
+    }
+
+    /// Make an empty map for the chunk to be generated into.
+    ///
+    /// Each attempt gets a different rng, so starting over doesn't make the same chunk again.
+    fn fresh_state(
+        layout: &LevelLayout,
+        coord: ICoord,
+        attempt: u64,
+    ) -> TileMap<Xoshiro256StarStar> {
+        let origin = chunk_origin(coord);
+        let to_local = |pos: ICoord| ICoord::new(pos.x - origin.x, pos.y - origin.y);
+        let if_here = |pos: ICoord| {
+            if tile_to_chunk(pos) == coord {
+                Some(to_local(pos))
+            } else {
+                None
+            }
         };
 
-        let coll = ColliderBuilder::cuboid(TILE_SCALE / 2.0, TILE_SCALE / 2.0)
-            .collision_groups(InteractionGroups::new(collider_groups::GROUP_WALLS, filter))
-            .build();
-        let rb = RigidBodyBuilder::new_static()
-            .translation(vector![pos.x as f32, pos.y as f32] * TILE_SCALE)
-            .build();
-        world.spawn_with_physics(physics, (ColoredBox(color),), coll, Some(rb));
+        let salt = chunk_salt(SALT_CHUNK, coord).wrapping_add(attempt);
+        let rng = level_rng(layout.seed, layout.depth, salt);
+        let gates = layout.gates(coord).into_iter().map(to_local).collect();
+
+        TileMap {
+            tiles: AHashMap::new(),
+            origin,
+            start_pos: if_here(layout.start_pos),
+            end_pos: if_here(layout.end_pos),
+            gates,
+            spawns: Vec::new(),
+            noise_seed: layout.noise_seed,
+            report: None,
+            rng,
+        }
     }
 
-    // Move player
-    {
-        let player_h = world.get_player().unwrap();
-        let rb_h = world.get::<HasRigidBody>(player_h).unwrap();
-        let rb = physics.rigid_bodies.get_mut(**rb_h).unwrap();
-        // It's ok to teleport the player to somewhere empty
-        rb.set_translation(
-            vector![state.start_pos.x as f32, state.start_pos.y as f32],
-            false,
+    /// Do a bit of generating. Returns `true` once there's nothing left to do.
+    pub fn step(&mut self) -> bool {
+        if let Some(stage) = self.stages.get_mut(self.current) {
+            if stage.step(&mut self.state, &self.layout.biome) {
+                self.current += 1;
+                if self.current >= self.stages.len() {
+                    self.check_solvable();
+                }
+            }
+        }
+        self.current >= self.stages.len()
+    }
+
+    /// Start the chunk over if validating it found it can't be solved.
+    fn check_solvable(&mut self) {
+        let report = match &self.state.report {
+            Some(report) if !report.solvable => report,
+            _ => return,
+        };
+
+        if self.attempt + 1 >= MAX_ATTEMPTS {
+            warn!(
+                "chunk {},{} of seed {} depth {} still can't be solved after {} tries: {}",
+                self.coord.x,
+                self.coord.y,
+                self.layout.seed,
+                self.layout.depth,
+                MAX_ATTEMPTS,
+                report
+            );
+            return;
+        }
+
+        self.attempt += 1;
+        self.state = Self::fresh_state(&self.layout, self.coord, self.attempt);
+        self.stages = self.layout.biome.stages();
+        self.current = 0;
+    }
+
+    /// Roughly how much of the generating is done, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        let total = self.stages.iter().map(|s| s.weight()).sum::<f32>();
+        let done = self.stages[..self.current]
+            .iter()
+            .map(|s| s.weight())
+            .sum::<f32>();
+        let current = self
+            .stages
+            .get(self.current)
+            .map_or(0.0, |s| s.weight() * s.progress());
+        (done + current) / total
+    }
+
+    /// Human-readable description of what we're doing right now.
+    pub fn description(&self) -> &'static str {
+        self.stages
+            .get(self.current)
+            .map_or("Done", |s| s.description())
+    }
+
+    /// Turn the finished generator into a chunk.
+    ///
+    /// Panics if `step` hasn't returned `true` yet.
+    pub fn finish(self) -> ChunkMap {
+        assert!(
+            self.current >= self.stages.len(),
+            "tried to finish a chunk that wasn't done generating"
         );
+
+        let origin = self.state.origin;
+        let mut tiles = Vec::with_capacity((CHUNK_SIZE * CHUNK_SIZE) as usize);
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                tiles.push(*self.state.tiles.get(&ICoord::new(x, y)).unwrap());
+            }
+        }
+
+        let mut spawns = self
+            .state
+            .spawns
+            .into_iter()
+            .map(|spawn| Spawn {
+                pos: origin + spawn.pos,
+                ..spawn
+            })
+            .collect::<Vec<_>>();
+        let end_pos = self.state.end_pos.map(|pos| origin + pos);
+        if let Some(end_pos) = end_pos {
+            spawns.push(Spawn {
+                pos: end_pos,
+                kind: SpawnKind::Shrine {
+                    level: self.layout.depth + 1,
+                },
+            });
+        }
+
+        ChunkMap {
+            coord: self.coord,
+            tiles,
+            spawns,
+            end_pos,
+        }
     }
-    let coll = ColliderBuilder::cuboid(0.4, 0.4)
-        .translation(vector![state.end_pos.x as f32, state.end_pos.y as f32])
-        .collision_groups(InteractionGroups::none())
-        .build();
-    world.spawn_with_physics(physics, (Shrine::new(1), ColoredBox(BLUE)), coll, None);
 }
 
-/// Tilemap generator using a persistent and generic rng.
+/// State of the cave carver between steps.
+pub struct CarveState {
+    /// If a pos in this set it ought to be empty
+    empties: AHashSet<ICoord>,
+    /// Cells that might be carved next
+    exposed: Vec<ICoord>,
+    billow: Billow,
+    simplex: SuperSimplex,
+}
+
+/// Rectangle of solid tiles that gets one collider.
+#[derive(Debug, Clone, Copy)]
+struct SolidRect {
+    min: ICoord,
+    width: isize,
+    height: isize,
+}
+
+/// Tilemap generator for one chunk, using a persistent and generic rng.
 ///
-/// The generator has 3 stages:
-/// - Carve caves
-/// - Place building seeds
-/// - Iterate buildings
-struct TileMap<R: Rng> {
+/// Positions in here are relative to the chunk's top-left corner.
+/// The `GenStage`s in `stages` take turns working on it.
+pub struct TileMap<R: Rng> {
     tiles: AHashMap<ICoord, Tile>,
-    start_pos: ICoord,
-    end_pos: ICoord,
+    /// Where the chunk's top-left corner is in the level.
+    origin: ICoord,
+    /// Where the player starts, if it's in this chunk.
+    start_pos: Option<ICoord>,
+    /// Where the exit is, if it's in this chunk.
+    end_pos: Option<ICoord>,
+    /// Tiles on the edges that line up with gates in the neighboring chunks.
+    gates: Vec<ICoord>,
+    /// Things to put in the world other than the exit shrine
+    spawns: Vec<Spawn>,
+    noise_seed: u32,
+    /// What validating the chunk found, once it's been validated
+    report: Option<MapReport>,
     rng: R,
 }
 
 impl<R: Rng> TileMap<R> {
-    fn generate(&mut self) {
-        self.fill();
-        self.carve_caves();
+    /// Tiles that have to be open and connected to each other:
+    /// the gates, and the start and end if they're here.
+    fn anchors(&self) -> Vec<ICoord> {
+        self.start_pos
+            .iter()
+            .chain(self.end_pos.iter())
+            .chain(self.gates.iter())
+            .copied()
+            .collect()
+    }
+
+    /// Find the cheapest route from `from` to any position where `is_goal` is true,
+    /// using Dijkstra's algorithm.
+    ///
+    /// `cost` is how much it costs to step onto a tile, or None if it can't be stepped on.
+    /// The route includes both ends. Returns an empty route if there isn't one.
+    fn cheapest_route(
+        &self,
+        from: ICoord,
+        is_goal: impl Fn(ICoord) -> bool,
+        cost: impl Fn(Tile) -> Option<u32>,
+    ) -> Vec<ICoord> {
+        let mut best = AHashMap::new();
+        let mut came_from = AHashMap::new();
+        let mut done = AHashSet::new();
+        let mut frontier = BinaryHeap::new();
+        best.insert(from, 0u32);
+        frontier.push(Reverse((0u32, from.x, from.y)));
+
+        let mut goal = None;
+        while let Some(Reverse((so_far, x, y))) = frontier.pop() {
+            let pos = ICoord::new(x, y);
+            if is_goal(pos) {
+                goal = Some(pos);
+                break;
+            }
+            if !done.insert(pos) {
+                continue;
+            }
+
+            for dir in Direction4::DIRECTIONS {
+                let next = pos + dir;
+                if let Some(step) = self.tiles.get(&next).and_then(|tile| cost(*tile)) {
+                    let total = so_far + step;
+                    if best.get(&next).map_or(true, |&b| total < b) {
+                        best.insert(next, total);
+                        came_from.insert(next, pos);
+                        frontier.push(Reverse((total, next.x, next.y)));
+                    }
+                }
+            }
+        }
+
+        let mut route = Vec::new();
+        let mut cursor = match goal {
+            Some(it) => it,
+            None => return route,
+        };
+        route.push(cursor);
+        while let Some(&prev) = came_from.get(&cursor) {
+            route.push(prev);
+            cursor = prev;
+        }
+        route
     }
 
     /// Fill everything with Rock
     fn fill(&mut self) {
-        for x in 0..WORLD_SIZE {
-            for y in 0..WORLD_SIZE {
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
                 let pos = ICoord::new(x, y);
                 let tile = Tile::Rock;
                 self.tiles.insert(pos, tile);
@@ -204,88 +698,205 @@ impl<R: Rng> TileMap<R> {
         }
     }
 
-    fn carve_caves(&mut self) {
+    fn start_carve(&mut self, params: &CaveParams) -> CarveState {
         // there are so many possibilities ...
         // for now we will use a Growing Tree algorithm, then make it less
         // jaggedy with a perlin noise.
 
-        let mut billow = Billow::new().set_seed(self.rng.gen());
-        billow.frequency = 5.0;
-        billow.octaves = 2;
-        let billow_reduced = ScaleBias {
-            source: &billow,
-            scale: 0.8,
-            bias: 0.0,
-        };
-        let simplex = SuperSimplex::new().set_seed(self.rng.gen());
-        let noiser = Blend::<'_, [f64; 2]>::new(&billow_reduced, &simplex, &simplex);
+        let mut billow = Billow::new().set_seed(self.noise_seed);
+        billow.frequency = params.billow_frequency;
+        billow.octaves = params.billow_octaves;
+        let simplex = SuperSimplex::new().set_seed(self.noise_seed.wrapping_add(1));
+
+        // Grow from everything that has to be open, so they're likely to be connected already
+        // Go through them in order; the set's order changes from run to run
+        let anchors = self.anchors();
+        let exposed = anchors
+            .iter()
+            .flat_map(|&pos| Direction4::DIRECTIONS.iter().map(move |dir| pos + *dir))
+            .collect::<Vec<_>>();
+        let empties = anchors.into_iter().collect::<AHashSet<_>>();
+
+        CarveState {
+            empties,
+            exposed,
+            billow,
+            simplex,
+        }
+    }
+
+    /// Run the Growing Tree carver for a while.
+    /// Returns `true` once it has nothing left to carve.
+    fn step_carve(&mut self, carve: &mut CarveState, params: &CaveParams) -> bool {
+        let distr = rand_distr::Exp::new(params.tree_twistiness).unwrap();
 
-        // Positive values are stone; negative are ground
-        let mut hardnesses = AHashMap::new();
+        let in_bounds =
+            |pos: ICoord| pos.x >= 0 && pos.x < CHUNK_SIZE && pos.y >= 0 && pos.y < CHUNK_SIZE;
 
-        {
-            // If a pos in this set it ought to be empty
-            let mut empties = AHashSet::new();
-            empties.insert(self.start_pos);
-            empties.insert(self.end_pos);
+        let CarveState {
+            empties, exposed, ..
+        } = carve;
+        for _ in 0..TREE_CELLS_PER_STEP {
+            if exposed.is_empty() {
+                return true;
+            }
+
+            let idx = self.rng.sample(distr);
+            let idx = (exposed.len() - 1).saturating_sub(idx.round() as usize);
+            let ex = exposed.remove(idx);
 
-            let mut exposed = empties
+            let open_adjacent_count = Direction4::DIRECTIONS
                 .iter()
-                .flat_map(|&pos| Direction4::DIRECTIONS.iter().map(move |dir| pos + *dir))
-                .collect::<Vec<_>>();
-
-            let distr = rand_distr::Exp::new(0.5f32).unwrap();
-
-            let in_bounds =
-                |pos: ICoord| pos.x >= 0 && pos.x < WORLD_SIZE && pos.y >= 0 && pos.y <= WORLD_SIZE;
-
-            while !exposed.is_empty() {
-                let idx = self.rng.sample(distr);
-                let idx = (exposed.len() - 1).saturating_sub(idx.round() as usize);
-                let ex = exposed.remove(idx);
-
-                let open_adjacent_count = Direction4::DIRECTIONS
-                    .iter()
-                    .filter(|dir| {
-                        let pos = ex + **dir;
-                        in_bounds(pos) && empties.contains(&pos)
-                    })
-                    .count();
-                if open_adjacent_count == 1 {
-                    // make this open!
-                    empties.insert(ex);
-                    for dir in Direction4::DIRECTIONS {
-                        let pos = ex + dir;
-                        if in_bounds(pos) && !empties.contains(&pos) {
-                            exposed.push(pos);
-                        }
+                .filter(|dir| {
+                    let pos = ex + **dir;
+                    in_bounds(pos) && empties.contains(&pos)
+                })
+                .count();
+            if open_adjacent_count == 1 {
+                // make this open!
+                empties.insert(ex);
+                for dir in Direction4::DIRECTIONS {
+                    let pos = ex + dir;
+                    if in_bounds(pos) && !empties.contains(&pos) {
+                        exposed.push(pos);
                     }
                 }
             }
+        }
 
-            for x in 0..WORLD_SIZE {
-                for y in 0..WORLD_SIZE {
-                    let pos = ICoord::new(x, y);
-                    let hardness = if empties.contains(&pos) { -0.7f32 } else { 0.4 };
-                    hardnesses.insert(pos, hardness);
-                }
+        exposed.is_empty()
+    }
+
+    /// Make the carved caves less jaggedy with noise,
+    /// for `count` columns starting at `column`.
+    fn step_harden(
+        &mut self,
+        carve: &CarveState,
+        column: isize,
+        count: isize,
+        params: &CaveParams,
+    ) {
+        let billow_reduced = ScaleBias {
+            source: &carve.billow,
+            scale: 0.8,
+            bias: 0.0,
+        };
+        let noiser = Blend::<'_, [f64; 2]>::new(&billow_reduced, &carve.simplex, &carve.simplex);
+
+        for x in column..(column + count).min(CHUNK_SIZE) {
+            for y in 0..CHUNK_SIZE {
+                let pos = ICoord::new(x, y);
+                // Positive values are stone; negative are ground
+                let mut hardness = if carve.empties.contains(&pos) {
+                    -0.7f32
+                } else {
+                    0.4
+                };
+
+                // Sample the noise by where we are in the level, so it carries on across borders
+                let sampler = [
+                    (self.origin.x + pos.x) as f64 / params.noise_scale,
+                    (self.origin.y + pos.y) as f64 / params.noise_scale,
+                ];
+                hardness += noiser.get(sampler) as f32 * params.noise_strength;
+
+                let tile = if hardness > params.rock_threshold {
+                    Tile::Rock
+                } else {
+                    Tile::Ground
+                };
+                self.tiles.insert(pos, tile);
             }
         }
+    }
+}
 
-        for (pos, hardness) in hardnesses.iter_mut() {
-            let sampler = [pos.x as f64 / 40.0, pos.y as f64 / 40.0];
-            let noise = noiser.get(sampler);
-            *hardness += noise as f32;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_world_round_trip() {
+        for y in -20..20 {
+            for x in -20..20 {
+                let tile = ICoord::new(x, y);
+                let center = tile_to_world(tile);
+                assert_eq!(world_to_tile(center), tile);
+
+                // Anywhere inside the tile goes back to its center
+                let nudge = TILE_SCALE * 0.45;
+                let offsets = [vec2(nudge, nudge), vec2(-nudge, nudge), vec2(nudge, -nudge)];
+                for &offset in offsets.iter() {
+                    assert_eq!(tile_to_world(world_to_tile(center + offset)), center);
+                }
+            }
         }
+    }
 
-        // Finally ...
-        for (pos, hardness) in hardnesses {
-            let tile = if hardness > 0.3 {
-                Tile::Rock
-            } else {
-                Tile::Ground
-            };
-            self.tiles.insert(pos, tile);
+    #[test]
+    fn start_and_spawns_are_open() {
+        // Make sure every kind of spawn actually got checked
+        let (mut shrines, mut lights, mut crystals) = (0, 0, 0);
+
+        for seed in 0..3 {
+            // One depth from each biome
+            for &depth in [0, 3, 6].iter() {
+                let map = LevelMap::generate(seed, depth);
+                let is_open = |pos: ICoord| matches!(map.get(pos), Some(tile) if !tile.is_solid());
+
+                assert!(
+                    is_open(map.start_pos),
+                    "seed {} depth {}: start {:?} is in a wall",
+                    seed,
+                    depth,
+                    map.start_pos
+                );
+                for spawn in &map.spawns {
+                    match spawn.kind {
+                        SpawnKind::Shrine { .. } => shrines += 1,
+                        // These come from prefabs
+                        SpawnKind::Light { .. } => lights += 1,
+                        SpawnKind::Crystal { .. } => crystals += 1,
+                    }
+                    assert!(
+                        is_open(spawn.pos),
+                        "seed {} depth {}: {:?} at {:?} is in a wall",
+                        seed,
+                        depth,
+                        spawn.kind,
+                        spawn.pos
+                    );
+                }
+            }
         }
+
+        assert!(
+            shrines > 0 && lights > 0 && crystals > 0,
+            "only saw {} shrines, {} lights and {} crystals",
+            shrines,
+            lights,
+            crystals
+        );
+    }
+
+    #[test]
+    fn unsolvable_chunks_start_over() {
+        let layout = LevelLayout::new(5, 0);
+        let mut generator = MapGenerator::new(&layout, ICoord::new(1, 1));
+        while !generator.step() {}
+        let first = generator.state.tiles.clone();
+
+        // Pretend validating it went badly
+        generator.state.report = Some(MapReport::default());
+        generator.check_solvable();
+        assert_eq!(generator.attempt, 1);
+        assert_eq!(generator.current, 0);
+
+        while !generator.step() {}
+        assert!(generator.state.report.as_ref().unwrap().solvable);
+        assert_ne!(
+            generator.state.tiles, first,
+            "starting over made the same chunk"
+        );
     }
 }
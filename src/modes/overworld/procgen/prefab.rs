@@ -0,0 +1,391 @@
+//! Hand-made set pieces that get stamped into generated maps.
+//!
+//! Prefabs live in `prefabs/` as text files like this:
+//!
+//! ```text
+//! name vault
+//! depth 2 99
+//! weight 1
+//! light 3 2 1.0 0.8 0.3
+//! tiles
+//! WWWWWWW
+//! W:::::W
+//! WWWDWWW
+//! ```
+//!
+//! - `depth <min> <max>` is the (inclusive) range of depths it can show up at.
+//! - `weight` is how likely it is to be picked compared to the others.
+//! - `exit <x> <y>` moves the level's exit shrine there. Only one prefab gets to do that,
+//!   and only in the chunk the exit was already in.
+//! - `light <x> <y> <r> <g> <b>` puts a light there.
+//!
+//! Tiles use the same characters as exported maps, plus `?` to leave whatever was there alone,
+//! and `D` for a door, which is a floor tile that gets a path dug from it to the caves.
+//! Prefabs without doors get connected from their first open tile instead.
+
+use ahash::AHashSet;
+use cogs_gamedev::grids::ICoord;
+use macroquad::prelude::{vec3, Vec3};
+use once_cell::sync::Lazy;
+use rand::{seq::SliceRandom, Rng};
+
+use super::{biome::Biome, Spawn, SpawnKind, Tile, TileMap, CHUNK_SIZE};
+
+/// How many random spots to try putting each prefab at.
+const PLACE_ATTEMPTS: usize = 64;
+/// At least this much of a prefab's footprint has to be open already.
+const MIN_OPEN_RATIO: f32 = 0.4;
+/// Prefabs aren't allowed to get this close to the start.
+const START_CLEARANCE: isize = 8;
+/// Cost for connecting paths to dig through rock.
+const CONNECT_ROCK_COST: u32 = 4;
+
+static PREFABS: Lazy<Vec<Prefab>> = Lazy::new(|| {
+    [
+        include_str!("prefabs/shrine_altar.txt"),
+        include_str!("prefabs/vault.txt"),
+        include_str!("prefabs/lamp_garden.txt"),
+    ]
+    .iter()
+    .map(|src| Prefab::parse(src))
+    .collect()
+});
+
+/// A set piece, parsed from one of the files in `prefabs/`.
+#[derive(Debug, Clone)]
+struct Prefab {
+    min_depth: u64,
+    max_depth: u64,
+    weight: u32,
+    width: isize,
+    height: isize,
+    /// Row-major. None means to leave the tile alone.
+    cells: Vec<Option<PrefabCell>>,
+    exit: Option<ICoord>,
+    lights: Vec<(ICoord, Vec3)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PrefabCell {
+    Tile(Tile),
+    Door,
+}
+
+impl PrefabCell {
+    fn tile(self) -> Tile {
+        match self {
+            PrefabCell::Tile(tile) => tile,
+            PrefabCell::Door => Tile::Floor,
+        }
+    }
+}
+
+impl Prefab {
+    /// Parse a prefab file. These are baked into the game, so this panics if it's malformed.
+    fn parse(src: &str) -> Self {
+        let mut lines = src.lines().map(str::trim_end);
+        let mut name = None;
+        let mut depths = (0, u64::MAX);
+        let mut weight = 1;
+        let mut exit = None;
+        let mut lights = Vec::new();
+
+        for line in &mut lines {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let num = |idx: usize| -> f32 {
+                words
+                    .get(idx)
+                    .and_then(|w| w.parse().ok())
+                    .unwrap_or_else(|| panic!("bad number in prefab line `{}`", line))
+            };
+            let coord = |idx: usize| ICoord::new(num(idx) as isize, num(idx + 1) as isize);
+
+            match words.as_slice() {
+                ["name", n] => name = Some(n.to_string()),
+                ["depth", ..] => depths = (num(1) as u64, num(2) as u64),
+                ["weight", ..] => weight = num(1) as u32,
+                ["exit", ..] => exit = Some(coord(1)),
+                ["light", ..] => lights.push((coord(1), vec3(num(3), num(4), num(5)))),
+                ["tiles"] => break,
+                [] => {}
+                _ => panic!("don't know what prefab line `{}` means", line),
+            }
+        }
+
+        let rows = lines.filter(|l| !l.is_empty()).collect::<Vec<_>>();
+        let name = name.expect("prefab is missing a name");
+        let height = rows.len() as isize;
+        let width = rows.first().map_or(0, |r| r.chars().count()) as isize;
+        assert!(
+            rows.iter().all(|r| r.chars().count() as isize == width),
+            "prefab {} isn't rectangular",
+            name
+        );
+
+        let cells = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '?' => None,
+                'D' => Some(PrefabCell::Door),
+                c => Some(PrefabCell::Tile(Tile::from_char(c).unwrap_or_else(|| {
+                    panic!("unknown tile `{}` in prefab {}", c, name)
+                }))),
+            })
+            .collect();
+
+        Prefab {
+            min_depth: depths.0,
+            max_depth: depths.1,
+            weight,
+            width,
+            height,
+            cells,
+            exit,
+            lights,
+        }
+    }
+
+    fn cell(&self, pos: ICoord) -> Option<PrefabCell> {
+        self.cells[(pos.y * self.width + pos.x) as usize]
+    }
+
+    /// Rotate it clockwise by 90 degrees.
+    fn rotated(&self) -> Prefab {
+        // (x, y) goes to (height - 1 - y, x)
+        let rotate = |pos: ICoord| ICoord::new(self.height - 1 - pos.y, pos.x);
+
+        let mut cells = vec![None; self.cells.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let to = rotate(ICoord::new(x, y));
+                cells[(to.y * self.height + to.x) as usize] = self.cell(ICoord::new(x, y));
+            }
+        }
+
+        Prefab {
+            width: self.height,
+            height: self.width,
+            cells,
+            exit: self.exit.map(rotate),
+            lights: self.lights.iter().map(|&(p, c)| (rotate(p), c)).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Flip it left-to-right.
+    fn mirrored(&self) -> Prefab {
+        let mirror = |pos: ICoord| ICoord::new(self.width - 1 - pos.x, pos.y);
+
+        let mut cells = vec![None; self.cells.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let to = mirror(ICoord::new(x, y));
+                cells[(to.y * self.width + to.x) as usize] = self.cell(ICoord::new(x, y));
+            }
+        }
+
+        Prefab {
+            cells,
+            exit: self.exit.map(mirror),
+            lights: self.lights.iter().map(|&(p, c)| (mirror(p), c)).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+impl<R: Rng> TileMap<R> {
+    /// Stamp up to `count` prefabs into open areas of the chunk,
+    /// and dig paths from them to the caves.
+    pub(super) fn place_prefabs(&mut self, count: usize, biome: &Biome) {
+        let depth = biome.depth;
+        let candidates = PREFABS
+            .iter()
+            .filter(|p| p.min_depth <= depth && depth <= p.max_depth)
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return;
+        }
+
+        let mut placed_exit = false;
+        // Tiles that prefabs have been stamped on, so others don't go on top of them
+        let mut taken = AHashSet::new();
+
+        for _ in 0..count {
+            let prefab = match candidates.choose_weighted(&mut self.rng, |p| p.weight) {
+                Ok(it) => *it,
+                Err(_) => return,
+            };
+            if prefab.exit.is_some() && (placed_exit || self.end_pos.is_none()) {
+                continue;
+            }
+
+            // Spin it around randomly
+            let mut prefab = prefab.clone();
+            for _ in 0..self.rng.gen_range(0..4) {
+                prefab = prefab.rotated();
+            }
+            if self.rng.gen_bool(0.5) {
+                prefab = prefab.mirrored();
+            }
+
+            let corner = (0..PLACE_ATTEMPTS).find_map(|_| {
+                let corner = ICoord::new(
+                    self.rng.gen_range(1..CHUNK_SIZE - 1 - prefab.width),
+                    self.rng.gen_range(1..CHUNK_SIZE - 1 - prefab.height),
+                );
+                if self.can_stamp(&prefab, corner, &taken) {
+                    Some(corner)
+                } else {
+                    None
+                }
+            });
+            let corner = match corner {
+                Some(it) => it,
+                None => continue,
+            };
+
+            self.stamp(&prefab, corner, &mut taken);
+            if let Some(exit) = prefab.exit {
+                self.end_pos = Some(corner + exit);
+                placed_exit = true;
+            }
+        }
+    }
+
+    /// Can the prefab go with its top-left corner here?
+    fn can_stamp(&self, prefab: &Prefab, corner: ICoord, taken: &AHashSet<ICoord>) -> bool {
+        let near_start = |pos: ICoord| {
+            self.start_pos.map_or(false, |start| {
+                (pos.x - start.x).abs() <= START_CLEARANCE
+                    && (pos.y - start.y).abs() <= START_CLEARANCE
+            })
+        };
+
+        let mut open = 0;
+        let mut footprint = 0;
+        for y in 0..prefab.height {
+            for x in 0..prefab.width {
+                if prefab.cell(ICoord::new(x, y)).is_none() {
+                    continue;
+                }
+                let pos = corner + ICoord::new(x, y);
+                if near_start(pos) || taken.contains(&pos) {
+                    return false;
+                }
+                // Don't stamp over buildings or paths
+                match self.tiles.get(&pos) {
+                    Some(Tile::Ground) => open += 1,
+                    Some(Tile::Rock) => {}
+                    _ => return false,
+                }
+                footprint += 1;
+            }
+        }
+
+        open as f32 >= footprint as f32 * MIN_OPEN_RATIO
+    }
+
+    /// Put the prefab down and connect it to the caves.
+    fn stamp(&mut self, prefab: &Prefab, corner: ICoord, taken: &mut AHashSet<ICoord>) {
+        let mut doors = Vec::new();
+        let mut first_open = None;
+        for y in 0..prefab.height {
+            for x in 0..prefab.width {
+                let cell = match prefab.cell(ICoord::new(x, y)) {
+                    Some(it) => it,
+                    None => continue,
+                };
+                let pos = corner + ICoord::new(x, y);
+                let tile = cell.tile();
+                self.tiles.insert(pos, tile);
+                taken.insert(pos);
+
+                if let PrefabCell::Door = cell {
+                    doors.push(pos);
+                }
+                if first_open.is_none() && !tile.is_solid() {
+                    first_open = Some(pos);
+                }
+            }
+        }
+
+        for &(pos, color) in &prefab.lights {
+            self.spawns.push(Spawn {
+                pos: corner + pos,
+                kind: SpawnKind::Light { color },
+            });
+        }
+
+        if doors.is_empty() {
+            doors.extend(first_open);
+        }
+        for door in doors {
+            // Dig out of the prefab to the nearest open ground that isn't part of it
+            let route = self.cheapest_route(
+                door,
+                |pos| {
+                    !taken.contains(&pos)
+                        && matches!(self.tiles.get(&pos), Some(Tile::Ground) | Some(Tile::Path))
+                },
+                |tile| match tile {
+                    Tile::Ground | Tile::Path => Some(1),
+                    Tile::Rock => Some(CONNECT_ROCK_COST),
+                    Tile::Floor | Tile::Wall => None,
+                },
+            );
+            for pos in route {
+                if !taken.contains(&pos) && matches!(self.tiles.get(&pos), Some(Tile::Rock)) {
+                    self.tiles.insert(pos, Tile::Path);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether the two prefabs would stamp exactly the same things.
+    fn same_layout(a: &Prefab, b: &Prefab) -> bool {
+        a.width == b.width
+            && a.height == b.height
+            && a.cells == b.cells
+            && a.exit == b.exit
+            && a.lights == b.lights
+    }
+
+    #[test]
+    fn turning_all_the_way_around_changes_nothing() {
+        for (idx, prefab) in PREFABS.iter().enumerate() {
+            let spun = (0..4).fold(prefab.clone(), |p, _| p.rotated());
+            assert!(
+                same_layout(prefab, &spun),
+                "prefab #{} rotated 4 times",
+                idx
+            );
+
+            let flipped = prefab.mirrored().mirrored();
+            assert!(
+                same_layout(prefab, &flipped),
+                "prefab #{} mirrored twice",
+                idx
+            );
+        }
+    }
+
+    #[test]
+    fn rotating_moves_exits_and_lights_with_the_tiles() {
+        let prefab = Prefab::parse("name test\nexit 2 0\nlight 0 1 1.0 1.0 1.0\ntiles\n..D\n:??\n");
+        let rotated = prefab.rotated();
+        assert_eq!((rotated.width, rotated.height), (2, 3));
+        assert_eq!(rotated.exit, Some(ICoord::new(1, 2)));
+        assert_eq!(rotated.cell(ICoord::new(1, 2)), Some(PrefabCell::Door));
+        assert_eq!(rotated.lights[0].0, ICoord::new(0, 0));
+        assert_eq!(
+            rotated.cell(ICoord::new(0, 0)),
+            Some(PrefabCell::Tile(Tile::Floor))
+        );
+    }
+}
@@ -0,0 +1,84 @@
+//! Command-line tools for looking at maps without starting the game.
+//!
+//! - `--map-preview <seed> <depth> [out]` generates every chunk of a level and writes it to `out`,
+//!   as a picture if it ends in `.png` and as text otherwise. With no `out` it prints the text.
+//! - `--map-check <file>` regenerates a map saved with `--map-preview`
+//!   and checks it comes out exactly the same.
+
+use anyhow::{bail, Context};
+
+use super::LevelMap;
+
+/// Run a map tool if the arguments ask for one.
+///
+/// Returns the exit code if a tool ran, or None if the game should start as normal.
+pub fn run_map_tool(args: &[String]) -> Option<i32> {
+    let result = match args.get(1).map(String::as_str) {
+        Some("--map-preview") => map_preview(&args[2..]),
+        Some("--map-check") => map_check(&args[2..]),
+        _ => return None,
+    };
+
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            1
+        }
+    })
+}
+
+fn map_preview(args: &[String]) -> anyhow::Result<()> {
+    let (seed, depth, out) = match args {
+        [seed, depth] => (seed, depth, None),
+        [seed, depth, out] => (seed, depth, Some(out)),
+        _ => bail!("usage: --map-preview <seed> <depth> [out.txt|out.png]"),
+    };
+    let seed = seed.parse().context("seed must be a number")?;
+    let depth = depth.parse().context("depth must be a number")?;
+
+    let map = LevelMap::generate(seed, depth);
+    match out {
+        Some(path) if path.ends_with(".png") => map.to_image().export_png(path),
+        Some(path) => {
+            std::fs::write(path, map.to_text()).with_context(|| format!("writing {}", path))?
+        }
+        None => print!("{}", map.to_text()),
+    }
+    Ok(())
+}
+
+fn map_check(args: &[String]) -> anyhow::Result<()> {
+    let path = match args {
+        [path] => path,
+        _ => bail!("usage: --map-check <file>"),
+    };
+    let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+    let golden = LevelMap::from_text(&text).with_context(|| format!("parsing {}", path))?;
+
+    let expected = golden.to_text();
+    let actual = LevelMap::generate(golden.seed, golden.depth).to_text();
+    if let Some((line_num, (want, got))) = expected
+        .lines()
+        .zip(actual.lines())
+        .enumerate()
+        .find(|(_, (want, got))| want != got)
+    {
+        bail!(
+            "{} differs at line {}:\nexpected: {}\n     got: {}",
+            path,
+            line_num + 1,
+            want,
+            got
+        );
+    }
+    if expected.lines().count() != actual.lines().count() {
+        bail!(
+            "{} has a different number of lines than the generated map",
+            path
+        );
+    }
+
+    println!("{} matches", path);
+    Ok(())
+}
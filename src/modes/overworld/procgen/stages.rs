@@ -0,0 +1,190 @@
+//! The stages a map goes through while it's being generated.
+
+use rand_xoshiro::Xoshiro256StarStar;
+
+use super::{biome::Biome, CarveState, TileMap, CHUNK_SIZE};
+
+/// How many columns of tiles get hardened per generation step.
+const HARDEN_COLUMNS_PER_STEP: isize = 8;
+
+/// One step of the map generation pipeline.
+///
+/// Stages are run in order by a `MapGenerator`, which calls `step` until it returns `true`
+/// and then moves on to the next one.
+pub trait GenStage {
+    /// Do a bit of work on the map. Returns `true` once this stage is done.
+    fn step(&mut self, map: &mut TileMap<Xoshiro256StarStar>, biome: &Biome) -> bool;
+
+    /// Roughly how much of this stage is done, from 0 to 1.
+    fn progress(&self) -> f32 {
+        0.0
+    }
+
+    /// Human-readable description of what this stage does.
+    fn description(&self) -> &'static str;
+
+    /// How long this stage takes compared to the others, for progress bars.
+    fn weight(&self) -> f32 {
+        1.0
+    }
+}
+
+/// Fill everything with rock.
+pub struct FillStage;
+
+impl GenStage for FillStage {
+    fn step(&mut self, map: &mut TileMap<Xoshiro256StarStar>, _biome: &Biome) -> bool {
+        map.fill();
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Filling the void"
+    }
+}
+
+/// Carve caves with a Growing Tree, then weather them with noise.
+pub struct CaveStage {
+    state: CaveProgress,
+}
+
+enum CaveProgress {
+    NotStarted,
+    Carving(CarveState),
+    /// Mixing noise into the carved caves, starting at the given column
+    Hardening(CarveState, isize),
+    Done,
+}
+
+impl CaveStage {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            state: CaveProgress::NotStarted,
+        }
+    }
+}
+
+impl GenStage for CaveStage {
+    fn step(&mut self, map: &mut TileMap<Xoshiro256StarStar>, biome: &Biome) -> bool {
+        let next = match &mut self.state {
+            CaveProgress::NotStarted => Some(CaveProgress::Carving(map.start_carve(&biome.caves))),
+            CaveProgress::Carving(carve) => {
+                if map.step_carve(carve, &biome.caves) {
+                    match std::mem::replace(&mut self.state, CaveProgress::Done) {
+                        CaveProgress::Carving(carve) => Some(CaveProgress::Hardening(carve, 0)),
+                        _ => unreachable!(),
+                    }
+                } else {
+                    None
+                }
+            }
+            CaveProgress::Hardening(carve, column) => {
+                map.step_harden(carve, *column, HARDEN_COLUMNS_PER_STEP, &biome.caves);
+                *column += HARDEN_COLUMNS_PER_STEP;
+                if *column >= CHUNK_SIZE {
+                    Some(CaveProgress::Done)
+                } else {
+                    None
+                }
+            }
+            CaveProgress::Done => None,
+        };
+        if let Some(next) = next {
+            self.state = next;
+        }
+
+        matches!(self.state, CaveProgress::Done)
+    }
+
+    fn progress(&self) -> f32 {
+        match &self.state {
+            CaveProgress::NotStarted => 0.0,
+            CaveProgress::Carving(carve) => {
+                // About half the map ends up carved out by the tree
+                let carved = carve.empties.len() as f32 / (CHUNK_SIZE * CHUNK_SIZE / 2) as f32;
+                carved.min(1.0) * 0.8
+            }
+            CaveProgress::Hardening(_, column) => 0.8 + *column as f32 / CHUNK_SIZE as f32 * 0.2,
+            CaveProgress::Done => 1.0,
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match &self.state {
+            CaveProgress::Hardening(..) | CaveProgress::Done => "Weathering rock",
+            _ => "Carving caves",
+        }
+    }
+
+    fn weight(&self) -> f32 {
+        // this is by far the slowest part
+        16.0
+    }
+}
+
+/// Put down buildings and link them with paths.
+///
+/// Does nothing if the biome has no buildings.
+pub struct BuildingStage;
+
+impl GenStage for BuildingStage {
+    fn step(&mut self, map: &mut TileMap<Xoshiro256StarStar>, biome: &Biome) -> bool {
+        if let Some(params) = &biome.buildings {
+            map.place_buildings(params);
+        }
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Raising buildings"
+    }
+}
+
+/// Stamp hand-made prefabs into the map.
+pub struct PrefabStage;
+
+impl GenStage for PrefabStage {
+    fn step(&mut self, map: &mut TileMap<Xoshiro256StarStar>, biome: &Biome) -> bool {
+        map.place_prefabs(biome.prefab_count, biome);
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Building set pieces"
+    }
+}
+
+/// Grow glowing crystals out of the rock.
+///
+/// Does nothing if the biome has no crystals.
+pub struct CrystalStage;
+
+impl GenStage for CrystalStage {
+    fn step(&mut self, map: &mut TileMap<Xoshiro256StarStar>, biome: &Biome) -> bool {
+        if let Some(params) = &biome.crystals {
+            map.place_crystals(params);
+        }
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Growing crystals"
+    }
+}
+
+/// Make sure you can get from the start to the end, and fix it if you can't.
+///
+/// The report gets left on the map, so the `MapGenerator` can start over if it's still unsolvable.
+pub struct ValidateStage;
+
+impl GenStage for ValidateStage {
+    fn step(&mut self, map: &mut TileMap<Xoshiro256StarStar>, _biome: &Biome) -> bool {
+        map.report = Some(map.validate());
+        true
+    }
+
+    fn description(&self) -> &'static str {
+        "Checking paths"
+    }
+}
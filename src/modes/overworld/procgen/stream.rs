@@ -0,0 +1,463 @@
+//! Keeping the chunks of a level near the player in the world, and the rest out of it.
+
+use ahash::AHashMap;
+use cogs_gamedev::grids::ICoord;
+use hecs::{Entity, World};
+use macroquad::prelude::{vec2, Color, Rect, Vec2, Vec3, BLUE};
+use nalgebra::vector;
+use rapier2d::prelude::{ActiveEvents, ColliderBuilder, InteractionGroups, RigidBodyBuilder};
+
+use crate::modes::overworld::{
+    cs::{
+        colored_box::ColoredBox,
+        light::{Illuminator, LightAnimation, LightFalloffKind},
+        physics::HasRigidBody,
+        player::Player,
+        shrine::Shrine,
+        tilemap::Tilemap,
+    },
+    physics::{collider_groups, PhysicsWorld},
+    WorldExt,
+};
+
+use super::{
+    chunk_origin, tile_to_chunk, tile_to_world, world_to_tile, ChunkMap, LevelLayout, MapJob,
+    SolidRect, SpawnKind, CHUNK_SIZE, TILE_SCALE,
+};
+
+/// Chunks within this many tiles of something we're keeping an eye on get loaded.
+const LOAD_DISTANCE: isize = 24;
+/// Loaded chunks are unloaded once they're this many tiles away from everything.
+/// This is more than `LOAD_DISTANCE` so pacing along a border doesn't load and unload over and over.
+const UNLOAD_DISTANCE: isize = 40;
+/// Most chunks to generate at the same time.
+const MAX_JOBS: usize = 4;
+/// How much of a chunk's progress is generating it (the rest is putting it in the world).
+const GENERATING_SHARE: f32 = 0.7;
+/// How many colliders to put in the world each frame as chunks load in.
+pub const COLLIDERS_PER_FRAME: usize = 256;
+
+/// The level the player is in, put in the world a chunk at a time around them.
+///
+/// Chunks get generated when they're needed and are forgotten again once they're far away,
+/// and only the ones near the player have colliders and spawns in the world and get drawn.
+/// Generating is deterministic, so a forgotten chunk comes back the same if it's needed again.
+pub struct LevelStreamer {
+    layout: LevelLayout,
+    /// Finished chunks, whether or not they're in the world.
+    generated: AHashMap<ICoord, ChunkMap>,
+    /// Chunks being generated in the background.
+    jobs: AHashMap<ICoord, MapJob>,
+    /// Chunks that are in the world, or on their way in.
+    loaded: AHashMap<ICoord, LoadedChunk>,
+    /// Chunks we wanted loaded as of the last update, nearest first.
+    wanted: Vec<ICoord>,
+    /// Entity with the `Tilemap` on it, once it's been made.
+    tilemap: Option<Entity>,
+}
+
+/// A chunk that's in the world, or partly in it.
+struct LoadedChunk {
+    /// Merged solid tiles to make colliders for.
+    rects: Vec<SolidRect>,
+    /// Index of the next rect to add.
+    cursor: usize,
+    /// Whether the spawns have been added yet. This happens after all the colliders are in.
+    spawned: bool,
+    /// Everything in the world that came from this chunk, so it can be taken out again.
+    entities: Vec<Entity>,
+}
+
+impl LevelStreamer {
+    pub fn new(seed: u64, depth: u64) -> Self {
+        Self {
+            layout: LevelLayout::new(seed, depth),
+            generated: AHashMap::new(),
+            jobs: AHashMap::new(),
+            loaded: AHashMap::new(),
+            wanted: Vec::new(),
+            tilemap: None,
+        }
+    }
+
+    /// Where the player starts, in world coordinates.
+    pub fn start(&self) -> Vec2 {
+        tile_to_world(self.layout.start_pos())
+    }
+
+    /// The area the whole level covers, in world coordinates.
+    pub fn bounds(&self) -> Rect {
+        // Tiles are centered on their world position, so the level starts half a tile back
+        let size = self.layout.size() as f32 * TILE_SCALE;
+        Rect::new(-TILE_SCALE / 2.0, -TILE_SCALE / 2.0, size, size)
+    }
+
+    /// The name of the biome this level is in.
+    pub fn biome_name(&self) -> &'static str {
+        self.layout.biome().name
+    }
+
+    /// Light that's everywhere in the level, as an RGB vector.
+    pub fn ambient_light(&self) -> Vec3 {
+        self.layout.biome().ambient.light()
+    }
+
+    /// Start generating the chunks around the start, without touching the world.
+    pub fn prefetch(&mut self) {
+        let wanted = self.chunks_near(&[self.layout.start_pos()]);
+        self.generate(&wanted);
+    }
+
+    /// Load the chunks near any of the `focuses` (in world coordinates),
+    /// and unload the ones that are far from all of them.
+    ///
+    /// This adds up to `budget` colliders to the world.
+    pub fn update(
+        &mut self,
+        focuses: &[Vec2],
+        budget: usize,
+        world: &mut World,
+        physics: &mut PhysicsWorld,
+    ) {
+        let focuses = focuses
+            .iter()
+            .map(|&pos| world_to_tile(pos))
+            .collect::<Vec<_>>();
+        self.wanted = self.chunks_near(&focuses);
+        let wanted = self.wanted.clone();
+        self.generate(&wanted);
+
+        let tilemap = match self.tilemap {
+            Some(it) => it,
+            None => {
+                let it = world.spawn((Tilemap::new(self.layout.biome().palette),));
+                self.tilemap = Some(it);
+                it
+            }
+        };
+
+        let far = self
+            .loaded
+            .keys()
+            .copied()
+            .filter(|&chunk| nearest(&focuses, chunk) > UNLOAD_DISTANCE)
+            .collect::<Vec<_>>();
+        for chunk in far {
+            let loaded = self.loaded.remove(&chunk).unwrap();
+            for e in loaded.entities {
+                // It might have been destroyed already, which is fine
+                let _ = world.despawn_with_physics(physics, e);
+            }
+            world
+                .get_mut::<Tilemap>(tilemap)
+                .unwrap()
+                .remove_chunk(chunk);
+        }
+        // Don't hang on to every chunk we've ever been near
+        self.generated
+            .retain(|&chunk, _| nearest(&focuses, chunk) <= UNLOAD_DISTANCE);
+
+        let mut budget = budget;
+        for chunk in wanted {
+            let map = match self.generated.get(&chunk) {
+                Some(it) => it,
+                None => continue,
+            };
+            let loaded = self.loaded.entry(chunk).or_insert_with(|| {
+                world
+                    .get_mut::<Tilemap>(tilemap)
+                    .unwrap()
+                    .insert_chunk(map.clone());
+                LoadedChunk {
+                    rects: map.solid_rects(),
+                    cursor: 0,
+                    spawned: false,
+                    entities: Vec::new(),
+                }
+            });
+            budget -= loaded.step(map, budget, world, physics);
+        }
+    }
+
+    /// Whether all the chunks we wanted as of the last update are all the way in the world.
+    pub fn is_ready(&self) -> bool {
+        !self.wanted.is_empty()
+            && self
+                .wanted
+                .iter()
+                .all(|chunk| self.loaded.get(chunk).map_or(false, |l| l.spawned))
+    }
+
+    /// Roughly how much of what we want is in the world, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        if self.wanted.is_empty() {
+            return 0.0;
+        }
+
+        let total = self
+            .wanted
+            .iter()
+            .map(|chunk| {
+                if let Some(loaded) = self.loaded.get(chunk) {
+                    GENERATING_SHARE + loaded.progress() * (1.0 - GENERATING_SHARE)
+                } else if let Some(job) = self.jobs.get(chunk) {
+                    job.progress() * GENERATING_SHARE
+                } else if self.generated.contains_key(chunk) {
+                    GENERATING_SHARE
+                } else {
+                    0.0
+                }
+            })
+            .sum::<f32>();
+        total / self.wanted.len() as f32
+    }
+
+    /// What the nearest chunk that isn't done yet is up to.
+    pub fn description(&self) -> &'static str {
+        for chunk in &self.wanted {
+            if let Some(job) = self.jobs.get(chunk) {
+                return job.description();
+            }
+            if !self.loaded.get(chunk).map_or(false, |l| l.spawned) {
+                return "Placing tiles";
+            }
+        }
+        "Done"
+    }
+
+    /// Move the player to the start of the level, and stop them.
+    ///
+    /// Only call this once the start's chunk is generated, like once `is_ready`.
+    pub fn place_player(&self, world: &mut World, physics: &mut PhysicsWorld) {
+        let start = self.layout.start_pos();
+        let chunk = self.generated.get(&tile_to_chunk(start));
+        debug_assert!(
+            chunk.is_some(),
+            "tried to place the player before the start's chunk was generated"
+        );
+        debug_assert!(
+            chunk.map_or(
+                true,
+                |chunk| matches!(chunk.get(start), Some(tile) if !tile.is_solid())
+            ),
+            "player would spawn inside a wall at {:?}",
+            start
+        );
+
+        let spawn = tile_to_world(start);
+        let player_h = world.get_player().unwrap();
+        let rb_h = world.get::<HasRigidBody>(player_h).unwrap();
+        let rb = physics.rigid_bodies.get_mut(**rb_h).unwrap();
+        // It's ok to teleport the player to somewhere empty
+        rb.set_translation(vector![spawn.x, spawn.y], false);
+        // and don't let them keep their momentum from the last level
+        rb.set_linvel(vector![0.0, 0.0], false);
+    }
+
+    /// Get the chunks within `LOAD_DISTANCE` of any of the tiles, nearest first.
+    fn chunks_near(&self, focuses: &[ICoord]) -> Vec<ICoord> {
+        let reach = LOAD_DISTANCE / CHUNK_SIZE + 1;
+        let mut chunks = Vec::new();
+        for &focus in focuses {
+            let center = tile_to_chunk(focus);
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    let chunk = ICoord::new(center.x + dx, center.y + dy);
+                    if self.layout.has_chunk(chunk)
+                        && nearest(focuses, chunk) <= LOAD_DISTANCE
+                        && !chunks.contains(&chunk)
+                    {
+                        chunks.push(chunk);
+                    }
+                }
+            }
+        }
+        chunks.sort_by_key(|&chunk| nearest(focuses, chunk));
+        chunks
+    }
+
+    /// Start generating any of these chunks that aren't already, and pick up finished ones.
+    ///
+    /// Jobs for chunks that aren't in `chunks` anymore get called off,
+    /// so they don't take up slots the chunks we do want need.
+    fn generate(&mut self, chunks: &[ICoord]) {
+        self.jobs.retain(|chunk, _| chunks.contains(chunk));
+
+        for &chunk in chunks {
+            if self.jobs.len() >= MAX_JOBS {
+                break;
+            }
+            if !self.generated.contains_key(&chunk) && !self.jobs.contains_key(&chunk) {
+                self.jobs
+                    .insert(chunk, MapJob::start(self.layout.clone(), chunk));
+            }
+        }
+
+        let mut finished = Vec::new();
+        for (&chunk, job) in self.jobs.iter_mut() {
+            if let Some(map) = job.poll() {
+                finished.push((chunk, map));
+            }
+        }
+        for (chunk, map) in finished {
+            self.jobs.remove(&chunk);
+            self.generated.insert(chunk, map);
+        }
+    }
+}
+
+impl LoadedChunk {
+    /// Add up to `budget` colliders to the world, and then the spawns once they're all in.
+    /// Returns how many colliders got added.
+    fn step(
+        &mut self,
+        map: &ChunkMap,
+        budget: usize,
+        world: &mut World,
+        physics: &mut PhysicsWorld,
+    ) -> usize {
+        let end = self.cursor.saturating_add(budget).min(self.rects.len());
+        for rect in &self.rects[self.cursor..end] {
+            // Tiles are centered on their positions, so the middle of the rect
+            // is halfway between the first and last tile.
+            let center = tile_to_world(rect.min)
+                + vec2((rect.width - 1) as f32, (rect.height - 1) as f32) * TILE_SCALE / 2.0;
+
+            let coll = ColliderBuilder::cuboid(
+                rect.width as f32 * TILE_SCALE / 2.0,
+                rect.height as f32 * TILE_SCALE / 2.0,
+            )
+            .collision_groups(InteractionGroups::new(
+                collider_groups::GROUP_WALLS,
+                collider_groups::FILTER_WALLS,
+            ))
+            .build();
+            let rb = RigidBodyBuilder::new_static()
+                .translation(vector![center.x, center.y])
+                .build();
+            self.entities
+                .push(world.spawn_with_physics(physics, (), coll, Some(rb)));
+        }
+        let added = end - self.cursor;
+        self.cursor = end;
+
+        if self.cursor < self.rects.len() || self.spawned {
+            return added;
+        }
+
+        for spawn in map.spawns() {
+            let pos = tile_to_world(spawn.pos);
+            debug_assert!(
+                matches!(map.get(spawn.pos), Some(tile) if !tile.is_solid()),
+                "{:?} would be inside a wall",
+                spawn
+            );
+
+            let e = match spawn.kind {
+                SpawnKind::Shrine { level } => {
+                    let coll = ColliderBuilder::cuboid(0.4, 0.4)
+                        .translation(vector![pos.x, pos.y])
+                        .sensor(true)
+                        .active_events(ActiveEvents::INTERSECTION_EVENTS)
+                        .collision_groups(InteractionGroups::new(
+                            collider_groups::GROUP_INTERACTABLES,
+                            collider_groups::FILTER_INTERACTABLES,
+                        ))
+                        .build();
+                    world.spawn_with_physics(
+                        physics,
+                        (Shrine::new(level), ColoredBox(BLUE)),
+                        coll,
+                        None,
+                    )
+                }
+                SpawnKind::Light { color } => {
+                    // Lights need a collider to know where they are, but it shouldn't touch anything
+                    let coll = ColliderBuilder::ball(0.1)
+                        .translation(vector![pos.x, pos.y])
+                        .sensor(true)
+                        .collision_groups(InteractionGroups::none())
+                        .build();
+                    world.spawn_with_physics(
+                        physics,
+                        (
+                            Illuminator::new(color, LightFalloffKind::Circular { m: 0.15 })
+                                .with_animation(LightAnimation {
+                                    flicker: 0.1,
+                                    flicker_speed: 6.0,
+                                    ..Default::default()
+                                }),
+                        ),
+                        coll,
+                        None,
+                    )
+                }
+                SpawnKind::Crystal { color } => {
+                    let coll = ColliderBuilder::cuboid(0.15, 0.25)
+                        .translation(vector![pos.x, pos.y])
+                        .sensor(true)
+                        .collision_groups(InteractionGroups::none())
+                        .build();
+                    world.spawn_with_physics(
+                        physics,
+                        (
+                            ColoredBox(Color::new(color.x, color.y, color.z, 1.0)),
+                            Illuminator::new(color * 0.8, LightFalloffKind::Circular { m: 0.2 })
+                                .with_animation(LightAnimation {
+                                    pulse: 0.3,
+                                    pulse_period: 3.0,
+                                    ..Default::default()
+                                }),
+                        ),
+                        coll,
+                        None,
+                    )
+                }
+            };
+            self.entities.push(e);
+        }
+        self.spawned = true;
+
+        added
+    }
+
+    /// How much of the chunk is in the world, from 0 to 1.
+    fn progress(&self) -> f32 {
+        if self.spawned {
+            1.0
+        } else if self.rects.is_empty() {
+            0.0
+        } else {
+            self.cursor as f32 / self.rects.len() as f32
+        }
+    }
+}
+
+/// Remove everything from the world except the player, to make way for a new level.
+pub fn clear_level(world: &mut World, physics: &mut PhysicsWorld) {
+    let mut remove = Vec::new();
+    for (e, player) in world.query_mut::<Option<&Player>>() {
+        if player.is_none() {
+            remove.push(e);
+        }
+    }
+    for e in remove {
+        world.despawn_with_physics(physics, e).unwrap();
+    }
+}
+
+/// How many tiles away the chunk is from the closest of the tiles,
+/// counting diagonal steps as one.
+fn nearest(focuses: &[ICoord], chunk: ICoord) -> isize {
+    let min = chunk_origin(chunk);
+    let max = ICoord::new(min.x + CHUNK_SIZE - 1, min.y + CHUNK_SIZE - 1);
+    focuses
+        .iter()
+        .map(|pos| {
+            let dx = (min.x - pos.x).max(pos.x - max.x).max(0);
+            let dy = (min.y - pos.y).max(pos.y - max.y).max(0);
+            dx.max(dy)
+        })
+        .min()
+        .unwrap_or(isize::MAX)
+}
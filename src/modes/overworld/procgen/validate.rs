@@ -0,0 +1,306 @@
+//! Making sure generated chunks can actually be played.
+//!
+//! Noise can seal off bits of the carved caves, and buildings can wall off passages,
+//! so after everything else is done we flood fill from one of the chunk's anchors
+//! (its gates, and the start and end if it has them) and dig tunnels to anything that got cut off.
+//! Tiny pockets get filled in instead, unless something has already been spawned in them.
+//! Since every chunk connects all its gates, the whole level ends up connected too.
+
+use std::{collections::VecDeque, fmt};
+
+use cogs_gamedev::grids::{Direction4, ICoord};
+use rand::Rng;
+
+use super::{Tile, TileMap, CHUNK_SIZE};
+
+/// Open regions smaller than this get filled in instead of dug out to.
+const MIN_REGION_SIZE: usize = 8;
+/// Cost for repair tunnels to dig through rock.
+const TUNNEL_ROCK_COST: u32 = 4;
+/// Cost for repair tunnels to knock through a building's wall.
+/// This is high so they'd rather dig around.
+const TUNNEL_WALL_COST: u32 = 24;
+
+/// What the validation pass found and did to a chunk.
+#[derive(Debug, Clone, Default)]
+pub struct MapReport {
+    /// How many separate open regions there were before repairs.
+    pub regions: usize,
+    /// How many of the anchors were stuck in rock and had to be cleared.
+    pub anchors_cleared: usize,
+    /// How many tunnels were dug to connect regions to the first anchor.
+    pub tunnels_dug: usize,
+    /// How many tiles the tunnels went through.
+    pub tiles_dug: usize,
+    /// How many tiny regions with nothing in them were filled in with rock.
+    pub pockets_filled: usize,
+    /// Whether all the anchors can reach each other after repairs.
+    pub solvable: bool,
+}
+
+impl fmt::Display for MapReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} regions; cleared {} anchors; dug {} tunnels through {} tiles; filled {} pockets; {}",
+            self.regions,
+            self.anchors_cleared,
+            self.tunnels_dug,
+            self.tiles_dug,
+            self.pockets_filled,
+            if self.solvable {
+                "solvable"
+            } else {
+                "NOT SOLVABLE"
+            }
+        )
+    }
+}
+
+impl<R: Rng> TileMap<R> {
+    /// Make sure every open tile can be reached from the anchors, fixing it if not.
+    pub(super) fn validate(&mut self) -> MapReport {
+        let mut report = MapReport::default();
+
+        let anchors = self.anchors();
+        let main_anchor = *anchors
+            .first()
+            .expect("every chunk has a gate, or is the whole level and has the start");
+        for &pos in &anchors {
+            if self.tiles.get(&pos).map_or(false, Tile::is_solid) {
+                self.tiles.insert(pos, Tile::Ground);
+                report.anchors_cleared += 1;
+            }
+        }
+
+        let regions = self.open_regions();
+        report.regions = regions.len();
+
+        let mut connected = vec![false; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        let idx = |pos: ICoord| (pos.y * CHUNK_SIZE + pos.x) as usize;
+
+        let main = regions
+            .iter()
+            .position(|region| region.contains(&main_anchor))
+            .expect("the anchor is open, so it must be in a region");
+        for &pos in &regions[main] {
+            connected[idx(pos)] = true;
+        }
+
+        for (region_idx, region) in regions.iter().enumerate() {
+            if region_idx == main || connected[idx(region[0])] {
+                // a tunnel to an earlier region might have gone through this one already
+                continue;
+            }
+
+            // Filling in a pocket with something spawned in it would bury it in the wall
+            let occupied = anchors
+                .iter()
+                .chain(self.spawns.iter().map(|spawn| &spawn.pos))
+                .any(|pos| region.contains(pos));
+            if region.len() < MIN_REGION_SIZE && !occupied {
+                for pos in region {
+                    self.tiles.insert(*pos, Tile::Rock);
+                }
+                report.pockets_filled += 1;
+                continue;
+            }
+
+            let route = self.cheapest_route(
+                region[0],
+                |pos| connected[idx(pos)],
+                |tile| match tile {
+                    Tile::Ground | Tile::Path | Tile::Floor => Some(1),
+                    Tile::Rock => Some(TUNNEL_ROCK_COST),
+                    Tile::Wall => Some(TUNNEL_WALL_COST),
+                },
+            );
+            if route.is_empty() {
+                continue;
+            }
+
+            for &pos in &route {
+                match self.tiles.get(&pos) {
+                    Some(Tile::Rock) => {
+                        self.tiles.insert(pos, Tile::Ground);
+                        report.tiles_dug += 1;
+                    }
+                    Some(Tile::Wall) => {
+                        // That's a new door
+                        self.tiles.insert(pos, Tile::Floor);
+                        report.tiles_dug += 1;
+                    }
+                    _ => {}
+                }
+            }
+            report.tunnels_dug += 1;
+
+            // The tunnel might have broken into other regions on the way,
+            // so flood out from it to mark everything it reached.
+            for pos in self.flood_fill(route[0]) {
+                connected[idx(pos)] = true;
+            }
+        }
+
+        let reachable = self.flood_fill(main_anchor);
+        report.solvable = anchors.iter().all(|a| reachable.contains(a));
+        report
+    }
+
+    /// Find all the separate regions of open tiles, in scanning order.
+    fn open_regions(&self) -> Vec<Vec<ICoord>> {
+        let mut seen = vec![false; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        let mut regions = Vec::new();
+
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let pos = ICoord::new(x, y);
+                let idx = (y * CHUNK_SIZE + x) as usize;
+                if seen[idx] || self.tiles.get(&pos).map_or(true, Tile::is_solid) {
+                    continue;
+                }
+
+                let region = self.flood_fill(pos);
+                for &pos in &region {
+                    seen[(pos.y * CHUNK_SIZE + pos.x) as usize] = true;
+                }
+                regions.push(region);
+            }
+        }
+
+        regions
+    }
+
+    /// Get every open tile reachable from `start`, which comes first.
+    fn flood_fill(&self, start: ICoord) -> Vec<ICoord> {
+        let mut seen = vec![false; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        let mut out = Vec::new();
+        let mut queue = VecDeque::new();
+
+        seen[(start.y * CHUNK_SIZE + start.x) as usize] = true;
+        queue.push_back(start);
+        while let Some(pos) = queue.pop_front() {
+            out.push(pos);
+            for dir in Direction4::DIRECTIONS {
+                let next = pos + dir;
+                if !matches!(self.tiles.get(&next), Some(tile) if !tile.is_solid()) {
+                    continue;
+                }
+                let idx = (next.y * CHUNK_SIZE + next.x) as usize;
+                if !seen[idx] {
+                    seen[idx] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use ahash::AHashSet;
+    use cogs_gamedev::grids::{Direction4, ICoord};
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoshiro256StarStar;
+
+    use super::super::{LevelLayout, LevelMap, MapGenerator};
+
+    /// Deeper than this is all the same biome.
+    const MAX_DEPTH: u64 = 9;
+
+    /// Random seeds from all over the range, each with a random depth.
+    fn seeds_and_depths(count: usize) -> Vec<(u64, u64)> {
+        let mut rng = Xoshiro256StarStar::seed_from_u64(0x5eed);
+        (0..count)
+            .map(|_| (rng.gen(), rng.gen_range(0..MAX_DEPTH)))
+            .collect()
+    }
+
+    #[test]
+    fn random_chunks_are_solvable() {
+        let mut rng = Xoshiro256StarStar::seed_from_u64(0xc4a7);
+        for (seed, depth) in seeds_and_depths(300) {
+            let layout = LevelLayout::new(seed, depth);
+            let chunk = ICoord::new(
+                rng.gen_range(0..layout.biome.chunks),
+                rng.gen_range(0..layout.biome.chunks),
+            );
+            let mut generator = MapGenerator::new(&layout, chunk);
+            while !generator.step() {}
+
+            // Checking the finished chunk again shouldn't find anything to fix
+            let report = generator.state.validate();
+            assert!(
+                report.solvable && report.tunnels_dug == 0,
+                "seed {} depth {} chunk {},{}: {}",
+                seed,
+                depth,
+                chunk.x,
+                chunk.y,
+                report
+            );
+        }
+    }
+
+    #[test]
+    fn spawns_are_never_filled_in() {
+        let mut rng = Xoshiro256StarStar::seed_from_u64(0x59a7);
+        for (seed, depth) in seeds_and_depths(300) {
+            let layout = LevelLayout::new(seed, depth);
+            let chunk = ICoord::new(
+                rng.gen_range(0..layout.biome.chunks),
+                rng.gen_range(0..layout.biome.chunks),
+            );
+            let mut generator = MapGenerator::new(&layout, chunk);
+            while !generator.step() {}
+            let map = generator.finish();
+
+            for spawn in map.spawns() {
+                assert!(
+                    matches!(map.get(spawn.pos), Some(tile) if !tile.is_solid()),
+                    "seed {} depth {} chunk {},{}: {:?} at {:?} is inside a wall",
+                    seed,
+                    depth,
+                    chunk.x,
+                    chunk.y,
+                    spawn.kind,
+                    spawn.pos
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn start_reaches_end_across_chunks() {
+        for (seed, depth) in seeds_and_depths(12) {
+            let map = LevelMap::generate(seed, depth);
+
+            let mut seen = AHashSet::new();
+            let mut queue = VecDeque::new();
+            seen.insert(map.start_pos);
+            queue.push_back(map.start_pos);
+            while let Some(pos) = queue.pop_front() {
+                for dir in Direction4::DIRECTIONS {
+                    let next = pos + dir;
+                    if matches!(map.get(next), Some(tile) if !tile.is_solid()) && seen.insert(next)
+                    {
+                        queue.push_back(next);
+                    }
+                }
+            }
+
+            assert!(
+                seen.contains(&map.end_pos),
+                "seed {} depth {}: can't get from {:?} to {:?}",
+                seed,
+                depth,
+                map.start_pos,
+                map.end_pos
+            );
+        }
+    }
+}
@@ -184,6 +184,13 @@ impl SpellCaster {
         }
     }
 
+    /// Throw away the pattern currently being drawn, if any, and go back to waiting.
+    ///
+    /// Patterns already finished are kept.
+    pub fn cancel_stroke(&mut self) {
+        self.state = PatternDrawState::Waiting;
+    }
+
     /// Get a reference to the spellcaster's patterns.
     pub fn patterns(&self) -> &[RawPattern] {
         self.patterns.as_slice()
@@ -10,7 +10,8 @@ use rapier2d::prelude::*;
 use crate::{
     modes::overworld::{
         cs::{
-            light::{Illuminator, LightFalloffKind},
+            facing::{Facing, FacingFrom},
+            light::{Illuminator, LightAnimation, LightFalloffKind},
             limited_time_offer::LimitedTimeOffer,
             particles::ParticleEmitter,
             physics::HasCollider,
@@ -62,6 +63,21 @@ impl RenderedSpell {
 
                     let timer = LimitedTimeOffer::new(2.0);
 
+                    // A little headlight so you can see where it's going
+                    let light = Illuminator::new(
+                        vec3(0.65, 0.4, 0.67),
+                        LightFalloffKind::Cone {
+                            m: 0.3,
+                            spread: 0.4,
+                            facing: direction,
+                        },
+                    )
+                    .with_animation(LightAnimation {
+                        fade_out: 0.5,
+                        ..Default::default()
+                    });
+                    let facing = Facing::new(direction, FacingFrom::Velocity);
+
                     let (dy, dx) = direction.sin_cos();
                     let vel = vector![dx, dy] * 12.0; // make up some velocity
                                                       // place this a little out from the caster so as to not hit them
@@ -75,7 +91,12 @@ impl RenderedSpell {
 
                     drop(parent_coll_h);
 
-                    world.spawn_with_physics(physics, (particles, proj, timer), coll, Some(rb));
+                    world.spawn_with_physics(
+                        physics,
+                        (particles, proj, timer, light, facing),
+                        coll,
+                        Some(rb),
+                    );
                 }
             }
             RenderedSpellKind::Light { pos } => {
@@ -108,7 +129,13 @@ impl RenderedSpell {
                 let particles = ParticleEmitter::new(config, false);
 
                 let light =
-                    Illuminator::new(vec3(1.0, 0.8, 0.7), LightFalloffKind::Circular { m: 0.05 });
+                    Illuminator::new(vec3(1.0, 0.8, 0.7), LightFalloffKind::Circular { m: 0.05 })
+                        .with_animation(LightAnimation {
+                            flicker: 0.15,
+                            flicker_speed: 8.0,
+                            fade_in: 0.3,
+                            ..Default::default()
+                        });
 
                 let collider = ColliderBuilder::ball(0.1)
                     .translation(pos.into())
@@ -187,6 +214,45 @@ impl RenderedSpell {
                     .build();
                 world.spawn_with_physics(physics, (particles,), collider, None);
             }
+            RenderedSpellKind::Spotlight { pos, towards } => {
+                let (dy, dx) = towards.sin_cos();
+                let config = EmitterConfig {
+                    amount: 10,
+                    initial_direction: vec2(dx, dy),
+                    initial_direction_spread: 0.3,
+                    initial_velocity: 0.5,
+                    initial_velocity_randomness: 0.5,
+                    size: 0.1,
+                    size_randomness: 0.5,
+                    shape: ParticleShape::Circle { subdivisions: 8 },
+                    one_shot: false,
+                    local_coords: true,
+                    colors_curve: ColorCurve {
+                        start: hexcolor(0xfabc37_55),
+                        mid: hexcolor(0xffde38_cc),
+                        end: hexcolor(0xfabc37_00),
+                    },
+                    lifetime: 1.0,
+                    ..Default::default()
+                };
+                let particles = ParticleEmitter::new(config, false);
+
+                let light = Illuminator::new(
+                    vec3(1.0, 0.9, 0.7),
+                    LightFalloffKind::Cone {
+                        m: 0.04,
+                        spread: 0.5,
+                        facing: towards,
+                    },
+                );
+                let facing = Facing::new(towards, FacingFrom::Fixed);
+
+                let collider = ColliderBuilder::ball(0.1)
+                    .translation(pos.into())
+                    .collision_groups(InteractionGroups::none())
+                    .build();
+                world.spawn_with_physics(physics, (particles, light, facing), collider, None);
+            }
         }
     }
 }
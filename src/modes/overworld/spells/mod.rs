@@ -239,6 +239,8 @@ pub enum RenderedSpellKind {
     Wayfinder { pos: Vec2, towards: f32 },
     /// Short particle effect that just goes poof
     Pointfinder { pos: Vec2 },
+    /// A light that shines in one direction
+    Spotlight { pos: Vec2, towards: f32 },
 }
 
 macro_rules! unwrap_arms {
@@ -297,6 +299,11 @@ impl RenderedSpellKind {
             (
                 Pointfinder => 1;
                 pos: SpellData::Position,
+            ),
+            (
+                Spotlight => 2;
+                pos: SpellData::Position,
+                towards: SpellData::Direction,
             )
         }
     }
@@ -310,6 +317,7 @@ impl SpellPrototype {
             SpellPrototype::Light => 1,
             SpellPrototype::Wayfinder => 2,
             SpellPrototype::Pointfinder => 1,
+            SpellPrototype::Spotlight => 2,
         }
     }
 
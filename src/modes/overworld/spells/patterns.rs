@@ -226,6 +226,8 @@ impl RawPattern {
             [Forward, LeftBack] => SpellPrototype::Wayfinder.into(),
             // Right arrow for wayfinder
             [Forward, RightBack] => SpellPrototype::Pointfinder.into(),
+            // Lightning bolt with a tail for a spotlight
+            [LeftBack, RightBack, Forward] => SpellPrototype::Spotlight.into(),
 
             // Otherwise it's junk
             _ => SpellData::Junk(self),
@@ -0,0 +1,225 @@
+//! Working out which parts of the world a light can see.
+//!
+//! Instead of sending a ray out to every pixel, we send rays out to the corners of everything
+//! near the light and join up where they land. That gives a polygon of everywhere the light reaches,
+//! and it only costs as much as there are corners nearby.
+
+use std::f32::consts::PI;
+
+use macroquad::prelude::{vec2, Rect, Vec2};
+
+/// How far to either side of each corner to send the extra rays that go past it.
+const CORNER_NUDGE: f32 = 0.0001;
+
+/// The area a light at `origin` can see, out to a square of the given radius.
+///
+/// It's star-shaped around the light, which makes checking if a point is in it cheap.
+pub struct VisibilityPolygon {
+    origin: Vec2,
+    /// Points around the edge, sorted by angle around the origin.
+    /// The angle is the first thing in the tuple.
+    points: Vec<(f32, Vec2)>,
+}
+
+impl VisibilityPolygon {
+    /// Find everything a light at `origin` can see when it's surrounded by the `occluders`.
+    ///
+    /// If the light is inside an occluder it can't see anything.
+    pub fn new(origin: Vec2, radius: f32, occluders: &[Rect]) -> Self {
+        if occluders.iter().any(|rect| contains_strictly(rect, origin)) {
+            return Self {
+                origin,
+                points: Vec::new(),
+            };
+        }
+
+        // Box in the light so every ray hits something
+        let bounds = Rect::new(
+            origin.x - radius,
+            origin.y - radius,
+            radius * 2.0,
+            radius * 2.0,
+        );
+        let mut segments = Vec::with_capacity((occluders.len() + 1) * 4);
+        push_edges(&mut segments, bounds);
+        for rect in occluders {
+            push_edges(&mut segments, *rect);
+        }
+
+        // Look right at each corner, and just to either side of it to see what's behind
+        let mut angles: Vec<f32> = segments
+            .iter()
+            .flat_map(|&(corner, _)| {
+                let angle = angle_to(corner - origin);
+                [angle - CORNER_NUDGE, angle, angle + CORNER_NUDGE]
+            })
+            .map(wrap_angle)
+            .collect();
+        angles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        angles.dedup();
+
+        let points = angles
+            .into_iter()
+            .filter_map(|angle| {
+                let dir = Vec2::new(angle.cos(), angle.sin());
+                let dist = segments
+                    .iter()
+                    .filter_map(|&(a, b)| ray_hits_segment(origin, dir, a, b))
+                    .fold(f32::INFINITY, f32::min);
+                // This is always finite thanks to the bounding box, but just in case
+                dist.is_finite().then(|| (angle, origin + dir * dist))
+            })
+            .collect();
+
+        Self { origin, points }
+    }
+
+    /// Where the light is.
+    pub fn origin(&self) -> Vec2 {
+        self.origin
+    }
+
+    /// Points around the edge of the polygon, going around the origin.
+    pub fn points(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.points.iter().map(|(_, point)| *point)
+    }
+
+    /// Is the given point lit?
+    pub fn contains(&self, point: Vec2) -> bool {
+        let count = self.points.len();
+        if count < 3 {
+            return false;
+        }
+
+        // Find the two edge points on either side of the point's angle...
+        let angle = angle_to(point - self.origin);
+        let next_idx = self.points.partition_point(|(a, _)| *a < angle) % count;
+        let prev_idx = (next_idx + count - 1) % count;
+        let prev = self.points[prev_idx].1;
+        let next = self.points[next_idx].1;
+
+        // ...and check it's on the same side of the line between them as the light is.
+        let edge = next - prev;
+        cross(edge, point - prev) * cross(edge, self.origin - prev) >= 0.0
+    }
+}
+
+/// The area the rect hides from a light at `origin`, including the rect itself,
+/// out past a square of the given radius.
+///
+/// It's convex, and the points go around it in order.
+pub fn shadow_of(origin: Vec2, rect: Rect, radius: f32) -> Vec<Vec2> {
+    let far = radius * 2.0;
+    if contains_strictly(&rect, origin) {
+        // Everything is behind it
+        return vec![
+            origin + vec2(-far, -far),
+            origin + vec2(far, -far),
+            origin + vec2(far, far),
+            origin + vec2(-far, far),
+        ];
+    }
+
+    // The corners, and where they'd land if you pushed them out away from the light
+    let mut edges = Vec::with_capacity(4);
+    push_edges(&mut edges, rect);
+    let mut points: Vec<Vec2> = edges
+        .into_iter()
+        .flat_map(|(corner, _)| {
+            let delta = corner - origin;
+            let away = if delta.length_squared() > 0.0 {
+                delta / delta.length()
+            } else {
+                Vec2::ZERO
+            };
+            [corner, origin + away * far]
+        })
+        .collect();
+    convex_hull(&mut points)
+}
+
+/// Find the convex hull of the points with the monotone chain algorithm.
+fn convex_hull(points: &mut [Vec2]) -> Vec<Vec2> {
+    points.sort_by(|a, b| {
+        a.x.partial_cmp(&b.x)
+            .unwrap()
+            .then(a.y.partial_cmp(&b.y).unwrap())
+    });
+
+    let mut hull: Vec<Vec2> = Vec::with_capacity(points.len() * 2);
+    // Do the bottom, then go back along the top
+    for pass in 0..2 {
+        let start = hull.len();
+        let ordered: Box<dyn Iterator<Item = &Vec2>> = if pass == 0 {
+            Box::new(points.iter())
+        } else {
+            Box::new(points.iter().rev())
+        };
+        for &point in ordered {
+            while hull.len() >= start + 2
+                && cross(
+                    hull[hull.len() - 1] - hull[hull.len() - 2],
+                    point - hull[hull.len() - 2],
+                ) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point is the first point of the next pass
+        hull.pop();
+    }
+    hull
+}
+
+/// Add the four edges of the rectangle as (start, end) pairs.
+///
+/// Every corner is the start of exactly one of them.
+fn push_edges(segments: &mut Vec<(Vec2, Vec2)>, rect: Rect) {
+    let tl = rect.point();
+    let tr = tl + vec2(rect.w, 0.0);
+    let br = tl + rect.size();
+    let bl = tl + vec2(0.0, rect.h);
+    segments.extend([(tl, tr), (tr, br), (br, bl), (bl, tl)]);
+}
+
+/// How far along the ray from `origin` in direction `dir` it hits the segment from `a` to `b`, if it does.
+fn ray_hits_segment(origin: Vec2, dir: Vec2, a: Vec2, b: Vec2) -> Option<f32> {
+    let along = b - a;
+    let denom = cross(dir, along);
+    if denom.abs() < f32::EPSILON {
+        // Parallel, so we just graze it
+        return None;
+    }
+    let to_a = a - origin;
+    let dist = cross(to_a, along) / denom;
+    let frac = cross(to_a, dir) / denom;
+    (dist >= 0.0 && (0.0..=1.0).contains(&frac)).then(|| dist)
+}
+
+/// Is the point inside the rect, and not just on its edge?
+///
+/// Lights pressed right up against a wall should still shine out the other way.
+fn contains_strictly(rect: &Rect, point: Vec2) -> bool {
+    point.x > rect.x && point.x < rect.x + rect.w && point.y > rect.y && point.y < rect.y + rect.h
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Angle of the vector, from -pi to pi.
+fn angle_to(v: Vec2) -> f32 {
+    v.y.atan2(v.x)
+}
+
+/// Put the angle back in -pi to pi.
+fn wrap_angle(angle: f32) -> f32 {
+    if angle > PI {
+        angle - 2.0 * PI
+    } else if angle < -PI {
+        angle + 2.0 * PI
+    } else {
+        angle
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{
+    assets::Assets,
+    boilerplates::{FrameInfo, Gamemode, Transition},
+    controls::{Control, InputCode, InputSubscriber},
+    utils::{
+        settings::Settings,
+        text::{Billboard, Markup, TextSpan},
+    },
+    HEIGHT, WIDTH,
+};
+
+use cogs_gamedev::controls::InputHandler;
+use macroquad::prelude::{vec2, Color, KeyCode, WHITE};
+use strum::IntoEnumIterator;
+
+use super::{ModeLoading, ModeLogo};
+
+/// How much the volume changes with one left/right press
+const VOLUME_STEP: f32 = 0.1;
+/// Width of the menu in patch9 tiles
+const MENU_WIDTH: usize = 8;
+
+/// Mode pushed over the overworld when the player pauses.
+///
+/// The game underneath keeps getting drawn, but because this doesn't update below it
+/// everything stays frozen until this is popped.
+pub struct ModePause {
+    /// Which page of the menu we're on.
+    page: PausePage,
+    /// Index of the highlighted entry on the page.
+    cursor: usize,
+    /// If this is Some, the next input the player presses gets bound to this control.
+    rebinding: Option<Control>,
+
+    /// Seed of the run we paused, for showing and restarting.
+    seed: u64,
+    /// Depth of the level we paused on.
+    depth: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PausePage {
+    Main,
+    Options,
+    Keybinds,
+}
+
+/// Things you can click on in the menu
+#[derive(Debug, Clone, Copy)]
+enum Entry {
+    Resume,
+    Options,
+    Restart,
+    CopySeed,
+    QuitToTitle,
+
+    Volume,
+    Fullscreen,
+    Keybinds,
+
+    Rebind(Control),
+
+    Back,
+}
+
+impl ModePause {
+    pub fn new(seed: u64, depth: u64) -> Self {
+        Self {
+            page: PausePage::Main,
+            cursor: 0,
+            rebinding: None,
+            seed,
+            depth,
+        }
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        match self.page {
+            PausePage::Main => vec![
+                Entry::Resume,
+                Entry::Options,
+                Entry::Restart,
+                Entry::CopySeed,
+                Entry::QuitToTitle,
+            ],
+            PausePage::Options => vec![
+                Entry::Volume,
+                Entry::Fullscreen,
+                Entry::Keybinds,
+                Entry::Back,
+            ],
+            PausePage::Keybinds => Control::iter()
+                .map(Entry::Rebind)
+                .chain(std::iter::once(Entry::Back))
+                .collect(),
+        }
+    }
+
+    fn title(&self) -> String {
+        match self.page {
+            PausePage::Main => format!("PAUSED\nSeed: {}\nDepth: {}", self.seed, self.depth),
+            PausePage::Options => "OPTIONS".to_owned(),
+            PausePage::Keybinds => "KEYBINDS".to_owned(),
+        }
+    }
+
+    fn label(&self, entry: Entry, settings: &Settings) -> String {
+        match entry {
+            Entry::Resume => "Resume".to_owned(),
+            Entry::Options => "Options".to_owned(),
+            Entry::Restart => "Restart with this seed".to_owned(),
+            Entry::CopySeed => "Copy seed".to_owned(),
+            Entry::QuitToTitle => "Quit to title".to_owned(),
+            Entry::Volume => format!("Volume < {:3.0}% >", settings.master_volume * 100.0),
+            Entry::Fullscreen => format!(
+                "Fullscreen: {}",
+                if settings.fullscreen { "on" } else { "off" }
+            ),
+            Entry::Keybinds => "Keybinds".to_owned(),
+            Entry::Rebind(control) => {
+                let bound = if self.rebinding == Some(control) {
+                    "...".to_owned()
+                } else {
+                    settings
+                        .binding_for(control)
+                        .map(|input| input.name())
+                        .unwrap_or_else(|| "-".to_owned())
+                };
+                format!("{:?}: {}", control, bound)
+            }
+            Entry::Back => "Back".to_owned(),
+        }
+    }
+
+    /// Make the billboard the menu is drawn on.
+    ///
+    /// Span 0 is the title; span `n + 1` is entry `n`.
+    fn billboard(&self, assets: &Assets) -> Billboard {
+        let font = assets.textures.fonts.small;
+        let markup = |color| Markup {
+            font,
+            color,
+            kerning: 1.0,
+            vert_space: 3.0,
+            wave: None,
+        };
+
+        let settings = Settings::get();
+        let mut text = vec![TextSpan::new(
+            format!("{}\n", self.title()),
+            markup(Color::new(1.0, 0.9, 0.6, 1.0)),
+        )];
+        for (idx, entry) in self.entries().into_iter().enumerate() {
+            let (prefix, color) = if idx == self.cursor {
+                ("> ", WHITE)
+            } else {
+                ("  ", Color::new(0.7, 0.7, 0.7, 1.0))
+            };
+            // Pad it out so you can click anywhere on the line
+            let label = format!("{}{:<24}\n", prefix, self.label(entry, &settings));
+            text.push(TextSpan::new(label, markup(color)));
+        }
+
+        // The title can be more than one line
+        let lines: usize = text
+            .iter()
+            .map(|span| span.text.matches('\n').count())
+            .sum();
+        let height = (lines * 8) / 16 + 2;
+        let tile_size = assets.textures.billboard_patch9.width() / 3.0;
+        Billboard::new(
+            text,
+            vec2(
+                WIDTH / 2.0 - MENU_WIDTH as f32 * tile_size / 2.0,
+                HEIGHT / 2.0 - height as f32 * tile_size / 2.0,
+            ),
+            vec2(8.0, 14.0),
+            assets.textures.billboard_patch9,
+            MENU_WIDTH,
+            height,
+        )
+    }
+
+    /// Do the thing the entry says to.
+    fn activate(&mut self, entry: Entry) -> Transition {
+        match entry {
+            Entry::Resume => return Transition::Pop,
+            Entry::Options => self.goto(PausePage::Options),
+            Entry::Restart => {
+                return Transition::PopNAndPush(2, vec![Box::new(ModeLoading::new(self.seed))]);
+            }
+            Entry::CopySeed => {
+                let gl = unsafe { macroquad::window::get_internal_gl() };
+                gl.quad_context.clipboard_set(&self.seed.to_string());
+            }
+            Entry::QuitToTitle => {
+                return Transition::PopNAndPush(2, vec![Box::new(ModeLogo::new())]);
+            }
+            Entry::Volume => {}
+            Entry::Fullscreen => Settings::modify(|s| s.fullscreen = !s.fullscreen),
+            Entry::Keybinds => self.goto(PausePage::Keybinds),
+            Entry::Rebind(control) => self.rebinding = Some(control),
+            Entry::Back => self.back(),
+        }
+        Transition::None
+    }
+
+    fn goto(&mut self, page: PausePage) {
+        self.page = page;
+        self.cursor = 0;
+    }
+
+    /// Go back one page.
+    fn back(&mut self) {
+        match self.page {
+            PausePage::Main => {}
+            PausePage::Options => self.goto(PausePage::Main),
+            PausePage::Keybinds => self.goto(PausePage::Options),
+        }
+    }
+}
+
+impl Gamemode for ModePause {
+    fn update(
+        &mut self,
+        controls: &InputSubscriber,
+        _frame_info: FrameInfo,
+        assets: &Assets,
+    ) -> Transition {
+        if let Some(control) = self.rebinding {
+            if let Some(input) = controls.last_input() {
+                // Escape always cancels so you can't lock yourself out of the menu
+                if input != InputCode::Key(KeyCode::Escape) || control == Control::Pause {
+                    Settings::modify(|s| s.rebind(control, input));
+                }
+                self.rebinding = None;
+            }
+            return Transition::None;
+        }
+
+        if controls.clicked_down(Control::Pause) {
+            return if self.page == PausePage::Main {
+                Transition::Pop
+            } else {
+                self.back();
+                Transition::None
+            };
+        }
+
+        let entries = self.entries();
+        if controls.clicked_down(Control::Up) {
+            self.cursor = (self.cursor + entries.len() - 1) % entries.len();
+        }
+        if controls.clicked_down(Control::Down) {
+            self.cursor = (self.cursor + 1) % entries.len();
+        }
+
+        let entry = entries[self.cursor];
+        if let Entry::Volume = entry {
+            if controls.clicked_down(Control::Left) {
+                Settings::modify(|s| s.master_volume -= VOLUME_STEP);
+            }
+            if controls.clicked_down(Control::Right) {
+                Settings::modify(|s| s.master_volume += VOLUME_STEP);
+            }
+        }
+
+        if controls.clicked_down(Control::Submit) {
+            return self.activate(entry);
+        }
+        if controls.clicked_down(Control::Click) {
+            let clicked = self
+                .billboard(assets)
+                .get_char_at_pixel(controls.mouse_pos(), 1.0);
+            if let Some((span_idx, _, _)) = clicked {
+                if span_idx >= 1 {
+                    self.cursor = span_idx - 1;
+                    return self.activate(entries[self.cursor]);
+                }
+            }
+        }
+
+        Transition::None
+    }
+
+    fn draw(&self, assets: &Assets, _frame_info: FrameInfo, _controls: &InputSubscriber) {
+        use macroquad::prelude::*;
+
+        // Dim the game underneath
+        draw_rectangle(0.0, 0.0, WIDTH, HEIGHT, Color::new(0.0, 0.0, 0.0, 0.6));
+
+        self.billboard(assets).draw();
+    }
+
+    fn draws_below(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    assets::Assets,
+    boilerplates::{FrameInfo, Gamemode, Transition},
+    controls::InputSubscriber,
+    HEIGHT, WIDTH,
+};
+
+use macroquad::prelude::{Color, RenderTarget, Texture2D};
+
+/// Ways to animate going from one mode to another
+#[derive(Debug, Clone, Copy)]
+pub enum TransitionStyle {
+    /// Blend smoothly from the old mode to the new one.
+    Crossfade,
+    /// The new mode slides in over the old one from the left.
+    Wipe,
+    /// Fade the old mode out to black, then fade the new one in.
+    FadeThroughBlack,
+}
+
+impl TransitionStyle {
+    /// Draw the two textures blended together, `t` of the way (from 0 to 1) through the transition.
+    pub fn composite(&self, from: Texture2D, to: Texture2D, t: f32) {
+        use macroquad::prelude::*;
+
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            TransitionStyle::Crossfade => {
+                draw_texture(from, 0.0, 0.0, WHITE);
+                draw_texture(to, 0.0, 0.0, Color::new(1.0, 1.0, 1.0, t));
+            }
+            TransitionStyle::Wipe => {
+                draw_texture(from, 0.0, 0.0, WHITE);
+                let width = (t * WIDTH).round();
+                draw_texture_ex(
+                    to,
+                    0.0,
+                    0.0,
+                    WHITE,
+                    DrawTextureParams {
+                        source: Some(Rect::new(0.0, 0.0, width, HEIGHT)),
+                        dest_size: Some(vec2(width, HEIGHT)),
+                        ..Default::default()
+                    },
+                );
+            }
+            TransitionStyle::FadeThroughBlack => {
+                let shown = if t < 0.5 { from } else { to };
+                draw_texture(shown, 0.0, 0.0, WHITE);
+                draw_rectangle(0.0, 0.0, WIDTH, HEIGHT, fade_black(t));
+            }
+        }
+    }
+}
+
+/// Color of the black overlay `t` of the way through a fade through black:
+/// clear at the ends and fully black in the middle.
+fn fade_black(t: f32) -> Color {
+    let alpha = 1.0 - (t.clamp(0.0, 1.0) * 2.0 - 1.0).abs();
+    Color::new(0.0, 0.0, 0.0, alpha)
+}
+
+/// Make a render target the size of the canvas, for drawing a whole frame into.
+pub fn canvas_target() -> RenderTarget {
+    use macroquad::prelude::*;
+
+    let target = render_target(WIDTH as u32, HEIGHT as u32);
+    target.texture.set_filter(FilterMode::Nearest);
+    target
+}
+
+/// Run `draw` with it drawing into `target` instead of the canvas.
+///
+/// This is handy for blending things that don't swap gamemodes, like going down to the next level.
+pub fn draw_into_target(target: RenderTarget, draw: impl FnOnce()) {
+    use macroquad::prelude::*;
+
+    push_camera_state();
+    // Same camera as the main loop's canvas
+    set_camera(&Camera2D {
+        render_target: Some(target),
+        zoom: vec2(2.0 / WIDTH, 2.0 / HEIGHT),
+        target: vec2(WIDTH / 2.0, HEIGHT / 2.0),
+        ..Default::default()
+    });
+    clear_background(BLACK);
+    draw();
+    pop_camera_state();
+}
+
+/// Wrapper mode that animates swapping out one mode for another.
+///
+/// Both modes are drawn to their own render targets and then blended together.
+/// Neither mode is updated until the transition is over, at which point this swaps
+/// itself out for the new mode.
+pub struct ModeTransition {
+    from: Box<dyn Gamemode>,
+    /// This is only None after the transition is over and we've given it away.
+    to: Option<Box<dyn Gamemode>>,
+
+    style: TransitionStyle,
+    /// How long the whole transition takes, in seconds.
+    duration: f32,
+    /// How far into the transition we are, in seconds.
+    elapsed: f32,
+
+    from_target: RenderTarget,
+    to_target: RenderTarget,
+}
+
+impl ModeTransition {
+    pub fn new(
+        from: Box<dyn Gamemode>,
+        to: Box<dyn Gamemode>,
+        style: TransitionStyle,
+        duration: f32,
+    ) -> Self {
+        Self {
+            from,
+            to: Some(to),
+            style,
+            duration,
+            elapsed: 0.0,
+            from_target: canvas_target(),
+            to_target: canvas_target(),
+        }
+    }
+
+    /// Draw the mode to the render target.
+    fn draw_into(
+        target: RenderTarget,
+        mode: &dyn Gamemode,
+        assets: &Assets,
+        frame_info: FrameInfo,
+        controls: &InputSubscriber,
+    ) {
+        draw_into_target(target, || mode.draw(assets, frame_info, controls));
+    }
+}
+
+impl Gamemode for ModeTransition {
+    fn update(
+        &mut self,
+        _controls: &InputSubscriber,
+        frame_info: FrameInfo,
+        _assets: &Assets,
+    ) -> Transition {
+        self.elapsed += frame_info.dt;
+        if self.elapsed >= self.duration {
+            if let Some(to) = self.to.take() {
+                return Transition::Swap(to);
+            }
+        }
+        Transition::None
+    }
+
+    fn draw(&self, assets: &Assets, frame_info: FrameInfo, controls: &InputSubscriber) {
+        let to = match &self.to {
+            Some(it) => it,
+            None => return,
+        };
+
+        Self::draw_into(
+            self.from_target,
+            self.from.as_ref(),
+            assets,
+            frame_info,
+            controls,
+        );
+        Self::draw_into(self.to_target, to.as_ref(), assets, frame_info, controls);
+
+        let t = if self.duration > 0.0 {
+            self.elapsed / self.duration
+        } else {
+            1.0
+        };
+        self.style
+            .composite(self.from_target.texture, self.to_target.texture, t);
+    }
+}
+
+impl Drop for ModeTransition {
+    fn drop(&mut self) {
+        self.from_target.delete();
+        self.to_target.delete();
+    }
+}
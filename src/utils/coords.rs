@@ -0,0 +1,106 @@
+//! Converting between the different kinds of coordinates.
+//!
+//! - Screen coordinates are pixels in the window, like macroquad's `mouse_position`.
+//! - Canvas coordinates are pixels on the `WIDTH`x`HEIGHT` canvas everything is drawn to,
+//!   which gets stretched and letterboxed to fit in the window.
+//! - World coordinates are physics units in the overworld, which depend on where the camera is looking.
+
+use macroquad::prelude::{screen_height, screen_width, vec2, Rect, Vec2};
+
+use crate::{utils::draw::width_height_deficit, HEIGHT, WIDTH};
+
+/// Turn a position in the window into a position on the canvas.
+pub fn screen_to_canvas(pos: Vec2) -> Vec2 {
+    let (wd, hd) = width_height_deficit();
+    let x = (pos.x - wd / 2.0) / ((screen_width() - wd) / WIDTH);
+    let y = (pos.y - hd / 2.0) / ((screen_height() - hd) / HEIGHT);
+    vec2(x, y)
+}
+
+/// Where the canvas is looking in the world.
+///
+/// `view` gets stretched over the whole canvas, so this works at any zoom.
+#[derive(Debug, Clone, Copy)]
+pub struct ViewTransform {
+    view: Rect,
+}
+
+impl ViewTransform {
+    /// Make a transform for a canvas showing the given part of the world.
+    pub fn new(view: Rect) -> Self {
+        Self { view }
+    }
+
+    /// How many canvas pixels across and down one world unit takes up.
+    pub fn scale(&self) -> Vec2 {
+        vec2(WIDTH, HEIGHT) / self.view.size()
+    }
+
+    /// Turn a position on the canvas into a position in the world.
+    pub fn canvas_to_world(&self, pos: Vec2) -> Vec2 {
+        self.view.point() + pos / self.scale()
+    }
+
+    /// Turn a position in the world into a position on the canvas.
+    ///
+    /// Only the tests need to go this way so far.
+    #[cfg(test)]
+    pub fn world_to_canvas(&self, pos: Vec2) -> Vec2 {
+        (pos - self.view.point()) * self.scale()
+    }
+
+    /// Turn a position in the window into a position in the world.
+    pub fn screen_to_world(&self, pos: Vec2) -> Vec2 {
+        self.canvas_to_world(screen_to_canvas(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A view like the camera's at the given zoom, with its corner at `corner`.
+    fn view_at(corner: Vec2, zoom: f32) -> ViewTransform {
+        let size = vec2(WIDTH, HEIGHT) / (16.0 * zoom);
+        ViewTransform::new(Rect::new(corner.x, corner.y, size.x, size.y))
+    }
+
+    fn assert_close(a: Vec2, b: Vec2) {
+        assert!((a - b).length() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn canvas_and_world_round_trip() {
+        let points = [
+            vec2(0.0, 0.0),
+            vec2(WIDTH, HEIGHT),
+            vec2(17.5, 203.25),
+            vec2(-40.0, 300.0),
+        ];
+        for zoom in [1.0, 2.0] {
+            let transform = view_at(vec2(10.0, -5.0), zoom);
+            for &canvas in &points {
+                let world = transform.canvas_to_world(canvas);
+                assert_close(transform.world_to_canvas(world), canvas);
+                assert_close(
+                    transform.canvas_to_world(transform.world_to_canvas(world)),
+                    world,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zoom_scales_around_the_corner() {
+        let middle = vec2(WIDTH, HEIGHT) / 2.0;
+
+        let transform = view_at(vec2(10.0, -5.0), 1.0);
+        assert_close(transform.canvas_to_world(Vec2::ZERO), vec2(10.0, -5.0));
+        assert_close(transform.canvas_to_world(middle), vec2(20.0, 2.5));
+
+        // Zoomed in twice as far, so the middle is half as far from the corner
+        let transform = view_at(vec2(10.0, -5.0), 2.0);
+        assert_close(transform.canvas_to_world(middle), vec2(15.0, -1.25));
+        assert_close(transform.world_to_canvas(vec2(11.0, -5.0)), vec2(32.0, 0.0));
+    }
+}
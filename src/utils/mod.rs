@@ -1,4 +1,6 @@
+pub mod coords;
 pub mod draw;
 pub mod profile;
 pub mod serdeflate;
+pub mod settings;
 pub mod text;
@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    sync::{RwLock, RwLockReadGuard},
+};
+
+use once_cell::sync::Lazy;
+
+use crate::controls::{Control, InputCode, InputSubscriber};
+
+static SETTINGS: Lazy<RwLock<Settings>> = Lazy::new(|| RwLock::new(Settings::default()));
+
+/// Things the player can change from the options menu.
+///
+/// These live in a global so any gamemode can get at them.
+/// Things that need to react to changes (like the `InputSubscriber` rebuilding its keybinds)
+/// should check the `revision`.
+#[derive(Clone)]
+pub struct Settings {
+    /// Volume everything is played at, from 0 to 1.
+    pub master_volume: f32,
+    /// Whether the window is fullscreen.
+    pub fullscreen: bool,
+    /// Which inputs map to which controls.
+    pub keybinds: HashMap<InputCode, Control>,
+
+    /// Goes up by one every time the settings are modified.
+    revision: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            master_volume: 0.8,
+            fullscreen: false,
+            keybinds: InputSubscriber::default_controls(),
+            revision: 0,
+        }
+    }
+}
+
+impl Settings {
+    /// Get read access to the settings.
+    ///
+    /// Don't hold onto this for long, or `modify` will deadlock.
+    pub fn get() -> RwLockReadGuard<'static, Settings> {
+        SETTINGS.read().unwrap()
+    }
+
+    /// Change the settings, and apply anything that has to be applied right away.
+    pub fn modify(f: impl FnOnce(&mut Settings)) {
+        let mut settings = SETTINGS.write().unwrap();
+        let was_fullscreen = settings.fullscreen;
+
+        f(&mut settings);
+        settings.master_volume = settings.master_volume.clamp(0.0, 1.0);
+        settings.revision += 1;
+
+        if settings.fullscreen != was_fullscreen {
+            let gl = unsafe { macroquad::window::get_internal_gl() };
+            gl.quad_context.set_fullscreen(settings.fullscreen);
+        }
+    }
+
+    /// How many times the settings have been changed.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Bind the control to the given input, removing any inputs bound to it before.
+    ///
+    /// If another control had that input, it gets this control's old inputs instead,
+    /// so you can't lock yourself out of something by rebinding over it.
+    pub fn rebind(&mut self, control: Control, input: InputCode) {
+        let old_inputs: Vec<InputCode> = self
+            .keybinds
+            .iter()
+            .filter(|(_, bound)| **bound == control)
+            .map(|(input, _)| *input)
+            .collect();
+        self.keybinds.retain(|_, bound| *bound != control);
+
+        if let Some(displaced) = self.keybinds.insert(input, control) {
+            for old in old_inputs {
+                if old != input {
+                    self.keybinds.insert(old, displaced);
+                }
+            }
+        }
+    }
+
+    /// Get an input bound to the given control, if there is one.
+    pub fn binding_for(&self, control: Control) -> Option<InputCode> {
+        self.keybinds.iter().find_map(|(input, bound)| {
+            if *bound == control {
+                Some(*input)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::prelude::KeyCode;
+
+    use super::*;
+
+    #[test]
+    fn rebinding_over_another_control_swaps() {
+        let mut settings = Settings::default();
+        settings.rebind(Control::Up, InputCode::Key(KeyCode::Enter));
+
+        assert_eq!(
+            settings.binding_for(Control::Up),
+            Some(InputCode::Key(KeyCode::Enter))
+        );
+        assert_eq!(
+            settings.binding_for(Control::Submit),
+            Some(InputCode::Key(KeyCode::W))
+        );
+    }
+
+    #[test]
+    fn rebinding_to_a_free_input_only_moves_that_control() {
+        let mut settings = Settings::default();
+        settings.rebind(Control::Lantern, InputCode::Key(KeyCode::L));
+
+        assert_eq!(
+            settings.binding_for(Control::Lantern),
+            Some(InputCode::Key(KeyCode::L))
+        );
+        assert_eq!(settings.keybinds.get(&InputCode::Key(KeyCode::F)), None);
+        assert_eq!(
+            settings.keybinds.len(),
+            InputSubscriber::default_controls().len()
+        );
+    }
+}